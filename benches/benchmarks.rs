@@ -17,6 +17,25 @@ fn spawn_process(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn reuse_process(b: &mut Bencher) {
+    let instructions = vec![
+        Instruction::LiteralUnsigned(37),
+        Instruction::AddUnsigned,
+    ];
+
+    let mut x = Process::new(&instructions);
+
+    b.iter(|| {
+        x.reset_with_args(&[Object::Unsigned(13)]).unwrap();
+
+        let r = x.run(64);
+
+        black_box(r);
+        black_box(x.stack());
+    });
+}
+
 #[bench]
 fn simple_addition(b: &mut Bencher) {
     b.iter(|| {
@@ -52,10 +71,27 @@ fn run_100k_cycles(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn run_100k_cycles_jump(b: &mut Bencher) {
+    b.iter(|| {
+        let instructions = vec![
+            Instruction::Jump(0),
+        ];
+
+        let mut x = Process::new(&instructions);
+
+        let r = x.run(100_000);
+
+        black_box(r);
+        black_box(x);
+    });
+}
+
 #[bench]
 fn intrinsic(b: &mut Bencher) {
-    fn nop(proc: &mut Process) {
+    fn nop(proc: &mut Process) -> Result<(), HaltReason> {
         black_box(proc);
+        Ok(())
     }
 
     b.iter(|| {
@@ -64,7 +100,7 @@ fn intrinsic(b: &mut Bencher) {
             Instruction::Intrinsic,
         ];
 
-        let intrinsics: &[fn(&mut Process)] = &[
+        let intrinsics: &[IntrinsicFn] = &[
             nop
         ];
 
@@ -0,0 +1,476 @@
+use core::cell::Cell;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use {HaltReason, Object};
+
+/// A handle to a compound object on a process's heap.
+///
+/// Handles are only valid until the heap is next collected, apart from the
+/// ones the collector updates; using a stale one fails with
+/// `InvalidReference`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeapRef {
+    at: u32,
+    // The number of collections the heap had been through when the handle
+    // was made.
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Array = 1,
+    Tuple = 2,
+    Bytes = 3,
+}
+
+// Every object starts with two header words: the kind, mark bit and length
+// packed into one `Object::Unsigned`, and a forwarding address used while
+// compacting. Items follow one per word; bytes are packed eight to a word.
+const HEADER: usize = 2;
+const KIND_MASK: u64 = 0b11;
+const MARK: u64 = 1 << 2;
+const LEN_SHIFT: u32 = 8;
+// Ends the list of objects waiting to have their items marked.
+const END: u64 = !0;
+
+#[cfg(feature = "alloc")]
+const MIN_OWNED: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    kind: Kind,
+    marked: bool,
+    len: usize,
+}
+
+impl Header {
+    fn words(&self) -> usize {
+        words_for(self.kind, self.len)
+    }
+}
+
+/// The number of arena words an object of `len` items or bytes occupies.
+pub(crate) fn words_for(kind: Kind, len: usize) -> usize {
+    match kind {
+        Kind::Array | Kind::Tuple => HEADER + len,
+        Kind::Bytes => HEADER + (len + 7) / 8,
+    }
+}
+
+enum Storage<'a> {
+    Borrowed(&'a [Cell<Object>]),
+    #[cfg(feature = "alloc")]
+    Owned(Vec<Cell<Object>>),
+}
+
+/// Objects are bump-allocated in an arena of `Object` words and addressed
+/// by the offset of their header. When the arena fills up, a mark-compact
+/// collection slides the live objects down and rewrites every reference.
+pub(crate) struct Heap<'a> {
+    storage: Storage<'a>,
+    top: usize,
+    // The most words an owned arena may grow to.
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    limit: usize,
+    generation: u32,
+}
+
+impl<'a> Clone for Heap<'a> {
+    /// Copies an allocated heap. A borrowed arena can't be copied, so the
+    /// clone of one is empty; see `copy_into`.
+    fn clone(&self) -> Self {
+        match self.storage {
+            Storage::Borrowed(_) => Heap {
+                storage: Storage::Borrowed(&[]),
+                top: 0,
+                limit: self.limit,
+                generation: self.generation,
+            },
+            #[cfg(feature = "alloc")]
+            Storage::Owned(ref words) => Heap {
+                storage: Storage::Owned(words.clone()),
+                top: self.top,
+                limit: self.limit,
+                generation: self.generation,
+            },
+        }
+    }
+}
+
+impl<'a> Heap<'a> {
+    /// Uses `arena` if one was given. With the `alloc` feature an empty
+    /// arena is replaced by one that grows as needed, up to `limit` words.
+    pub(crate) fn new(arena: &'a [Cell<Object>], limit: usize) -> Heap<'a> {
+        #[cfg(feature = "alloc")]
+        let storage = if arena.is_empty() {
+            Storage::Owned(Vec::new())
+        } else {
+            Storage::Borrowed(arena)
+        };
+
+        #[cfg(not(feature = "alloc"))]
+        let storage = Storage::Borrowed(arena);
+
+        Heap {
+            storage,
+            top: 0,
+            limit,
+            generation: 0,
+        }
+    }
+
+    /// Copies the heap into `arena`, where its objects keep their addresses,
+    /// or fails with `OutOfMemory` if they don't fit.
+    pub(crate) fn copy_into<'b>(&self, arena: &'b [Cell<Object>]) -> Result<Heap<'b>, HaltReason> {
+        if arena.len() < self.top {
+            return Err(HaltReason::OutOfMemory);
+        }
+
+        for (to, from) in arena.iter().zip(&self.words()[..self.top]) {
+            to.set(from.get());
+        }
+
+        Ok(Heap {
+            storage: Storage::Borrowed(arena),
+            top: self.top,
+            limit: self.limit,
+            generation: self.generation,
+        })
+    }
+
+    fn words(&self) -> &[Cell<Object>] {
+        match self.storage {
+            Storage::Borrowed(words) => words,
+            #[cfg(feature = "alloc")]
+            Storage::Owned(ref words) => words,
+        }
+    }
+
+    fn word(&self, at: usize) -> u64 {
+        match self.words()[at].get() {
+            Object::Unsigned(x) => x,
+            _ => 0,
+        }
+    }
+
+    fn set_word(&self, at: usize, x: u64) {
+        self.words()[at].set(Object::Unsigned(x));
+    }
+
+    pub(crate) fn fits(&self, words: usize) -> bool {
+        self.top.saturating_add(words) <= self.words().len()
+    }
+
+    /// Whether a freshly collected heap should grow rather than fill up
+    /// again straight away.
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    pub(crate) fn wants_growth(&self, words: usize) -> bool {
+        match self.storage {
+            Storage::Borrowed(_) => false,
+            #[cfg(feature = "alloc")]
+            Storage::Owned(ref arena) => self.top.saturating_add(words).saturating_mul(2) > arena.len(),
+        }
+    }
+
+    /// Makes room for at least `words` more words, if the arena is ours and
+    /// that keeps it within the limit.
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    pub(crate) fn grow(&mut self, words: usize) -> bool {
+        match self.storage {
+            Storage::Borrowed(_) => false,
+            #[cfg(feature = "alloc")]
+            Storage::Owned(ref mut arena) => {
+                let needed = match self.top.checked_add(words) {
+                    Some(needed) if needed <= self.limit => needed,
+                    _ => return false,
+                };
+
+                let mut len = arena.len() * 2;
+                if len < MIN_OWNED {
+                    len = MIN_OWNED;
+                }
+                if len < needed * 2 {
+                    len = needed * 2;
+                }
+                if len > self.limit {
+                    len = self.limit;
+                }
+                arena.resize(len, Cell::new(Object::Unsigned(0)));
+                true
+            }
+        }
+    }
+
+    fn handle(&self, at: usize) -> HeapRef {
+        HeapRef {
+            at: at as u32,
+            generation: self.generation,
+        }
+    }
+
+    fn header(&self, r: HeapRef) -> Result<Header, HaltReason> {
+        if r.generation != self.generation {
+            return Err(HaltReason::InvalidReference);
+        }
+        self.header_at(r.at as usize)
+    }
+
+    fn header_at(&self, at: usize) -> Result<Header, HaltReason> {
+        if at + HEADER > self.top {
+            return Err(HaltReason::InvalidReference);
+        }
+
+        let word = self.word(at);
+        let kind = match word & KIND_MASK {
+            1 => Kind::Array,
+            2 => Kind::Tuple,
+            3 => Kind::Bytes,
+            _ => return Err(HaltReason::InvalidReference),
+        };
+        let header = Header {
+            kind,
+            marked: word & MARK != 0,
+            len: (word >> LEN_SHIFT) as usize,
+        };
+
+        if at + header.words() > self.top {
+            return Err(HaltReason::InvalidReference);
+        }
+        Ok(header)
+    }
+
+    fn set_header(&self, at: usize, header: Header) {
+        let mark = if header.marked { MARK } else { 0 };
+        self.set_word(at, header.kind as u64 | mark | (header.len as u64) << LEN_SHIFT);
+    }
+
+    /// Allocates an object, which the caller must have made room for.
+    pub(crate) fn alloc(&mut self, kind: Kind, len: usize) -> Object {
+        let at = self.top;
+        let header = Header {
+            kind,
+            marked: false,
+            len,
+        };
+
+        self.top += header.words();
+        self.set_header(at, header);
+        for word in &self.words()[at + 1..self.top] {
+            word.set(Object::Unsigned(0));
+        }
+
+        Object::Ref(self.handle(at))
+    }
+
+    /// Allocates an array or tuple holding `items`, which the caller must
+    /// have made room for.
+    pub(crate) fn alloc_items(&mut self, kind: Kind, items: &[Object]) -> Object {
+        let obj = self.alloc(kind, items.len());
+        let at = self.top - items.len();
+        for (word, item) in self.words()[at..].iter().zip(items) {
+            word.set(*item);
+        }
+        obj
+    }
+
+    /// Allocates a byte array holding `len` bytes of `from` starting at
+    /// `start`. The caller must have made room and checked the range.
+    pub(crate) fn alloc_slice(&mut self, from: HeapRef, start: usize, len: usize) -> Object {
+        let obj = self.alloc(Kind::Bytes, len);
+        if let Object::Ref(to) = obj {
+            for i in 0..len {
+                let byte = self.byte(from, start + i);
+                self.set_byte(to, i, byte);
+            }
+        }
+        obj
+    }
+
+    pub(crate) fn len(&self, r: HeapRef) -> Result<usize, HaltReason> {
+        Ok(self.header(r)?.len)
+    }
+
+    pub(crate) fn kind(&self, r: HeapRef) -> Result<Kind, HaltReason> {
+        Ok(self.header(r)?.kind)
+    }
+
+    /// The length of a byte array, or a type error for any other object.
+    pub(crate) fn bytes_len(&self, r: HeapRef) -> Result<usize, HaltReason> {
+        let header = self.header(r)?;
+        if header.kind != Kind::Bytes {
+            return Err(HaltReason::TypeError);
+        }
+        Ok(header.len)
+    }
+
+    // `byte` and `set_byte` leave checking the object and index to the caller.
+    pub(crate) fn byte(&self, r: HeapRef, idx: usize) -> u8 {
+        let word = self.word(r.at as usize + HEADER + idx / 8);
+        (word >> (8 * (idx % 8))) as u8
+    }
+
+    pub(crate) fn set_byte(&self, r: HeapRef, idx: usize, byte: u8) {
+        let at = r.at as usize + HEADER + idx / 8;
+        let shift = 8 * (idx % 8);
+        let word = self.word(at) & !(0xff << shift);
+        self.set_word(at, word | u64::from(byte) << shift);
+    }
+
+    pub(crate) fn index(&self, r: HeapRef, idx: u64) -> Result<Object, HaltReason> {
+        let header = self.header(r)?;
+        if idx >= header.len as u64 {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+
+        let idx = idx as usize;
+        Ok(match header.kind {
+            Kind::Array | Kind::Tuple => self.words()[r.at as usize + HEADER + idx].get(),
+            Kind::Bytes => Object::Unsigned(self.byte(r, idx).into()),
+        })
+    }
+
+    pub(crate) fn set_index(&mut self, r: HeapRef, idx: u64, value: Object) -> Result<(), HaltReason> {
+        let header = self.header(r)?;
+        if header.kind == Kind::Tuple {
+            return Err(HaltReason::TypeError);
+        }
+        if idx >= header.len as u64 {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+
+        let idx = idx as usize;
+        match header.kind {
+            Kind::Bytes => {
+                let byte = match value {
+                    Object::Unsigned(x) if x <= 0xff => x as u8,
+                    _ => return Err(HaltReason::TypeError),
+                };
+                self.set_byte(r, idx, byte);
+            }
+            _ => self.words()[r.at as usize + HEADER + idx].set(value),
+        }
+        Ok(())
+    }
+
+    // Marks the object `obj` refers to, if any. Arrays and tuples are pushed
+    // onto `worklist`, which is threaded through their forwarding words, so
+    // their items get marked in turn.
+    fn mark(&self, obj: Object, worklist: &mut u64) {
+        let r = match obj {
+            Object::Ref(r) => r,
+            _ => return,
+        };
+
+        let mut header = match self.header(r) {
+            Ok(header) => header,
+            Err(_) => return,
+        };
+        if header.marked {
+            return;
+        }
+
+        let at = r.at as usize;
+        header.marked = true;
+        self.set_header(at, header);
+        if header.kind != Kind::Bytes {
+            self.set_word(at + 1, *worklist);
+            *worklist = at as u64;
+        }
+    }
+
+    // Calls `f` with the offset and header of every object in the arena.
+    fn each_object<F: FnMut(usize, Header)>(&self, mut f: F) {
+        let mut at = 0;
+        while at < self.top {
+            let header = match self.header_at(at) {
+                Ok(header) => header,
+                Err(_) => return,
+            };
+            f(at, header);
+            at += header.words();
+        }
+    }
+
+    fn forwarded(&self, obj: Object) -> Object {
+        match obj {
+            Object::Ref(r) => match self.header(r) {
+                Ok(ref header) if header.marked => Object::Ref(HeapRef {
+                    at: self.word(r.at as usize + 1) as u32,
+                    generation: self.generation.wrapping_add(1),
+                }),
+                _ => obj,
+            },
+            _ => obj,
+        }
+    }
+
+    /// Frees every object not reachable from the roots, compacting the
+    /// survivors. `roots` is called twice, first to mark through the roots and
+    /// then to rewrite them with their objects' new addresses. Any other
+    /// handles become stale.
+    pub(crate) fn collect<F>(&mut self, mut roots: F)
+        where F: FnMut(&mut dyn FnMut(&mut Object)) {
+        let mut worklist = END;
+        roots(&mut |obj| {
+            self.mark(*obj, &mut worklist);
+        });
+
+        while worklist != END {
+            let at = worklist as usize;
+            worklist = self.word(at + 1);
+
+            let words = match self.header_at(at) {
+                Ok(header) => header.words(),
+                Err(_) => continue,
+            };
+            for item in &self.words()[at + HEADER..at + words] {
+                self.mark(item.get(), &mut worklist);
+            }
+        }
+
+        let mut free = 0;
+        self.each_object(|at, header| {
+            if header.marked {
+                self.set_word(at + 1, free as u64);
+                free += header.words();
+            }
+        });
+
+        roots(&mut |obj| {
+            *obj = self.forwarded(*obj);
+        });
+        self.each_object(|at, header| {
+            if !header.marked || header.kind == Kind::Bytes {
+                return;
+            }
+            for item in &self.words()[at + HEADER..at + header.words()] {
+                item.set(self.forwarded(item.get()));
+            }
+        });
+
+        let mut at = 0;
+        let mut to = 0;
+        while at < self.top {
+            let mut header = match self.header_at(at) {
+                Ok(header) => header,
+                Err(_) => break,
+            };
+            let words = header.words();
+
+            if header.marked {
+                for i in 1..words {
+                    let word = self.words()[at + i].get();
+                    self.words()[to + i].set(word);
+                }
+                header.marked = false;
+                self.set_header(to, header);
+                to += words;
+            }
+            at += words;
+        }
+        self.top = to;
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
@@ -9,21 +9,40 @@ extern crate failure;
 
 extern crate heapless;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 use heapless::Vec;
 use heapless::consts::*;
 
+use core::cell::Cell;
+use core::cmp;
 use core::convert::TryFrom;
 use core::convert::TryInto;
 
+mod snapshot;
+mod replay;
+mod heap;
+mod string;
+
+pub use snapshot::SnapshotError;
+pub use replay::{IntrinsicCall, Recorder, Replayer, LogRecorder, LogReplayer};
+pub use heap::HeapRef;
+pub use string::StrRef;
+
 #[derive(Fail, Debug)]
 #[fail(display = "Type Mismatch")]
 pub struct TypeMismatchError;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Objects are ordered by variant, in declaration order, then by value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Object {
     Unsigned(u64),
     Signed(i64),
     Bool(bool),
+    Ref(HeapRef),
+    Str(StrRef),
+    Nil,
 }
 
 impl TryFrom<Object> for u64 {
@@ -59,12 +78,174 @@ impl TryFrom<Object> for bool {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl TryFrom<Object> for HeapRef {
+    type Error = TypeMismatchError;
+
+    fn try_from(value: Object) -> Result<Self, TypeMismatchError> {
+        match value {
+            Object::Ref(x) => Ok(x),
+            _ => Err(TypeMismatchError{}),
+        }
+    }
+}
+
+impl TryFrom<Object> for StrRef {
+    type Error = TypeMismatchError;
+
+    fn try_from(value: Object) -> Result<Self, TypeMismatchError> {
+        match value {
+            Object::Str(x) => Ok(x),
+            _ => Err(TypeMismatchError{}),
+        }
+    }
+}
+
+// `Nil` stands for a missing value, so converting to `Option` maps it to
+// `None` and anything else to `Some`.
+macro_rules! option_objects {
+    ($($t:ty),*) => {
+        $(
+            impl TryFrom<Object> for Option<$t> {
+                type Error = TypeMismatchError;
+
+                fn try_from(value: Object) -> Result<Self, TypeMismatchError> {
+                    match value {
+                        Object::Nil => Ok(None),
+                        x => Ok(Some(x.try_into()?)),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+option_objects!(u64, i64, bool, HeapRef, StrRef);
+
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(x) => x.into(),
+            None => Object::Nil,
+        }
+    }
+}
+
+impl From<u64> for Object {
+    fn from(value: u64) -> Self {
+        Object::Unsigned(value)
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Signed(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Bool(value)
+    }
+}
+
+impl From<HeapRef> for Object {
+    fn from(value: HeapRef) -> Self {
+        Object::Ref(value)
+    }
+}
+
+impl From<StrRef> for Object {
+    fn from(value: StrRef) -> Self {
+        Object::Str(value)
+    }
+}
+
+/// A fixed number of typed values read off the top of the stack.
+///
+/// Tuples are taken in push order, so for `(A, B)` the value pushed first
+/// (deeper in the stack) is `A` and the top of the stack is `B`.
+pub trait FromObjects: Sized {
+    const COUNT: usize;
+
+    fn from_objects(objects: &[Object]) -> Result<Self, HaltReason>;
+}
+
+/// A fixed number of typed values to be pushed onto the stack, in order.
+pub trait IntoObjects {
+    const COUNT: usize;
+
+    fn into_objects<F: FnMut(Object)>(self, push: F);
+}
+
+macro_rules! scalar_objects {
+    ($($t:ty),*) => {
+        $(
+            impl FromObjects for $t {
+                const COUNT: usize = 1;
+
+                fn from_objects(objects: &[Object]) -> Result<Self, HaltReason> {
+                    match objects[0].try_into() {
+                        Ok(x) => Ok(x),
+                        Err(_) => Err(HaltReason::TypeError),
+                    }
+                }
+            }
+
+            impl IntoObjects for $t {
+                const COUNT: usize = 1;
+
+                fn into_objects<F: FnMut(Object)>(self, mut push: F) {
+                    push(self.into());
+                }
+            }
+        )*
+    }
+}
+
+scalar_objects!(u64, i64, bool, HeapRef, StrRef, Object);
+scalar_objects!(Option<u64>, Option<i64>, Option<bool>, Option<HeapRef>, Option<StrRef>);
+
+macro_rules! tuple_objects {
+    ($count:expr; $($name:ident: $idx:tt),*) => {
+        impl<$($name),*> FromObjects for ($($name,)*)
+            where $($name: TryFrom<Object>),* {
+            const COUNT: usize = $count;
+
+            fn from_objects(objects: &[Object]) -> Result<Self, HaltReason> {
+                Ok(($(
+                    match $name::try_from(objects[$idx]) {
+                        Ok(x) => x,
+                        Err(_) => return Err(HaltReason::TypeError),
+                    },
+                )*))
+            }
+        }
+
+        impl<$($name),*> IntoObjects for ($($name,)*)
+            where $($name: Into<Object>),* {
+            const COUNT: usize = $count;
+
+            fn into_objects<F: FnMut(Object)>(self, mut push: F) {
+                $(push(self.$idx.into());)*
+            }
+        }
+    }
+}
+
+tuple_objects!(1; A: 0);
+tuple_objects!(2; A: 0, B: 1);
+tuple_objects!(3; A: 0, B: 1, C: 2);
+tuple_objects!(4; A: 0, B: 1, C: 2, D: 3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instruction {
     LiteralUnsigned(u16),
     LiteralSigned(i16),
     LiteralBool(bool),
     LiteralIndexed(u16),
+    LiteralNil,
+    /// Pops a value and pushes whether it was `Nil`.
+    IsNil,
 
     AddUnsigned,
     SubtractUnsigned,
@@ -78,6 +259,36 @@ pub enum Instruction {
     DivideSigned,
     ModulusSigned,
 
+    MinUnsigned,
+    MaxUnsigned,
+    /// `[x, low, high] -> [x clamped to low..=high]`. If `low` is greater
+    /// than `high`, `low` wins.
+    ClampUnsigned,
+    /// Leaves its operand unchanged.
+    AbsUnsigned,
+    /// Fails with `Overflow` on anything but zero.
+    NegateUnsigned,
+
+    MinSigned,
+    MaxSigned,
+    ClampSigned,
+    // These fail with `Overflow` on `i64::MIN`.
+    AbsSigned,
+    NegateSigned,
+
+    // 128-bit values take two stack slots, with the high half pushed first.
+    // Signed values have a `Signed` high half and an `Unsigned` low half.
+    /// `[x, y] -> [high, low]`
+    WideMultiplyUnsigned,
+    WideMultiplySigned,
+    /// `[x, y] -> [high]`
+    MulHiUnsigned,
+    MulHiSigned,
+    /// `[high, low, divisor] -> [quotient, remainder]`, failing if the
+    /// quotient doesn't fit in 64 bits.
+    WideDivideUnsigned,
+    WideDivideSigned,
+
     BitAnd,
     BitOr,
     BitNot,
@@ -86,6 +297,30 @@ pub enum Instruction {
     BitRShift,
     BitLRot,
     BitRRot,
+    PopCount,
+    LeadingZeros,
+    TrailingZeros,
+    ByteSwap,
+    BitReverse,
+    /// `[x, offset, width] -> [field]`. Bits past the top of `x` read as 0.
+    BitExtract,
+    /// `[x, field, offset, width] -> [x]`, replacing the bits of `x` that
+    /// `BitExtract` would read with the low bits of `field`.
+    BitInsert,
+
+    // Signed variants take an `Unsigned` shift amount. `BitRShiftSigned`
+    // is an arithmetic shift, filling with the sign bit.
+    BitAndSigned,
+    BitOrSigned,
+    BitNotSigned,
+    BitXorSigned,
+    BitLShiftSigned,
+    BitRShiftSigned,
+
+    // Reinterpret the low 8, 16 or 32 bits of an `Unsigned` as a `Signed`.
+    SignExtend8,
+    SignExtend16,
+    SignExtend32,
 
     LogAnd,
     LogOr,
@@ -106,12 +341,96 @@ pub enum Instruction {
     GtEqSigned,
     LtEqSigned,
 
+    // Compare any two objects by value, so strings are equal if their bytes
+    // are, whether they're literals or byte arrays, and arrays and tuples if
+    // their items are. Objects nested more than 32 deep, as in a cycle, fail
+    // with `StackOverflow`.
+    Eq,
+    Neq,
+    /// `[x, y] -> [ordering]`, pushing -1, 0 or 1 as an `Object::Signed`.
+    /// Objects are ordered by kind: unsigned, signed, bool, array, tuple,
+    /// string, then nil. Within a kind strings, arrays and tuples are ordered
+    /// lexicographically and everything else by value.
+    Compare,
+
     Branch,
     BranchTrue,
+    BranchFalse,
+    /// `[cond, a, b] -> [a]` if `cond` is true, otherwise `[b]`.
+    Select,
+
+    // Relative variants take a signed offset from the branching instruction.
+    BranchRelative,
+    BranchTrueRelative,
+    CallRelative,
+
+    // Immediate variants carry an absolute target inline.
+    Jump(u16),
+    JumpIfTrue(u16),
+    JumpIfFalse(u16),
+    CallImm(u16),
+
+    LoadGlobal(u16),
+    StoreGlobal(u16),
+
+    LoadU8,
+    LoadU16,
+    LoadU32,
+    LoadU64,
+    StoreU8,
+    StoreU16,
+    StoreU32,
+    StoreU64,
+
+    // Compound values, which live on the process's heap.
+    MakeArray(u8),
+    MakeTuple(u8),
+    MakeBytes,
+    Length,
+    Index,
+    SetIndex,
+
+    /// `LiteralStr(n)` pushes byte-string literal `n`. The other string
+    /// instructions take either a literal or a heap byte array.
+    LiteralStr(u16),
+    StrLength,
+    /// `[string, index] -> [byte]`
+    StrByte,
+    /// `[string, start, end] -> [slice]`. Slices of byte arrays are copies.
+    StrSlice,
+    /// `[a, b] -> [ordering]`, pushing -1, 0 or 1 as an `Object::Signed`.
+    StrCompare,
+    // `[buffer, offset, value] -> [buffer, offset]`: these write into the
+    // byte array `buffer` at `offset`, and push the offset just past the end
+    // of what they wrote.
+    StrConcat,
+    FormatUnsigned,
+    FormatSigned,
+    // `[string] -> [value, true]`, or `[false]` if it isn't a decimal number.
+    ParseUnsigned,
+    ParseSigned,
+
+    /// `Switch(table)` pops an unsigned selector and jumps through the jump
+    /// table at literal `table`: the entry count, the default target, then
+    /// one target per entry, all `Object::Unsigned`.
+    Switch(u16),
 
     Call,
     Ret,
+    /// `TailCall(args)` calls like `Call`, but replaces the current frame,
+    /// keeping only the top `args` values of its operand stack.
+    TailCall(u8),
+
+    /// Function prologue: `Enter(args, locals, results)` claims the top
+    /// `args` stack values as arguments, reserves `locals` local slots and
+    /// makes `Ret` keep only `results` values.
+    Enter(u8, u8, u8),
+    LoadArg(u8),
+    StoreArg(u8),
+    LoadLocal(u8),
+    StoreLocal(u8),
 
+    // Aliases for `StoreReg(0..4)` and `LoadReg(0..4)` respectively.
     PushSlot1,
     PushSlot2,
     PushSlot3,
@@ -122,6 +441,12 @@ pub enum Instruction {
     PopSlot3,
     PopSlot4,
 
+    StoreReg(u8),
+    /// Copies the top of the stack into a register without popping it.
+    StoreRegKeep(u8),
+    LoadReg(u8),
+    ClearReg(u8),
+
     Intrinsic,
 }
 
@@ -150,20 +475,136 @@ pub enum HaltReason {
     InvalidIntrinsic,
     #[fail(display = "Invalid Literal")]
     InvalidLiteral,
+    #[fail(display = "Intrinsic Recording Full")]
+    RecordingFull,
+    #[fail(display = "Replay Diverged From Recording")]
+    ReplayDivergence,
+    #[fail(display = "Unknown Export")]
+    UnknownExport,
+    #[fail(display = "Wrong Number Of Arguments Or Results")]
+    ArityMismatch,
+    #[fail(display = "Frame Instruction Outside Of A Call")]
+    NoFrame,
+    #[fail(display = "Invalid Argument Or Local")]
+    InvalidLocal,
+    #[fail(display = "Read Uninitialised Local")]
+    EmptyLocal,
+    #[fail(display = "Invalid Switch Table")]
+    InvalidSwitchTable,
+    #[fail(display = "Memory Access Out Of Bounds")]
+    MemoryOutOfBounds,
+    #[fail(display = "Invalid Global")]
+    InvalidGlobal,
+    #[fail(display = "Read Uninitialised Global")]
+    EmptyGlobal,
+    #[fail(display = "Invalid Register")]
+    InvalidRegister,
+    #[fail(display = "Invalid Heap Reference")]
+    InvalidReference,
+    #[fail(display = "Index Out Of Bounds")]
+    IndexOutOfBounds,
+    #[fail(display = "Out Of Memory")]
+    OutOfMemory,
+    #[fail(display = "Division By Zero")]
+    DivisionByZero,
+    #[fail(display = "Arithmetic Overflow")]
+    Overflow,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StackFrame {
-    ip: usize
+    ip: usize,
+    // Index into the operand stack of the frame's first argument.
+    base: usize,
+    args: usize,
+    // Index into `Process::locals` of the frame's first local.
+    locals: usize,
+    // Set by `Enter`; `Ret` then unwinds the operand stack to `base` plus
+    // this many results.
+    results: Option<usize>,
+}
+
+impl StackFrame {
+    fn new(ip: usize, base: usize, locals: usize) -> StackFrame {
+        StackFrame {
+            ip,
+            base,
+            args: 0,
+            locals,
+            results: None,
+        }
+    }
 }
 
 pub struct Process<'a> {
     ip: usize,
     stack: Vec<Object, U32>,
     callstack: Vec<StackFrame, U32>,
+    locals: Vec<Option<Object>, U64>,
     code: &'a [Instruction],
-    scratch: [Option<Object>; 4],
-    intrinsics: &'a [fn(&mut Process)],
+    scratch: Vec<Option<Object>, U16>,
+    intrinsics: &'a [IntrinsicFn],
     literals: &'a [Object],
+    strings: &'a [&'a [u8]],
+    exports: &'a [Export<'a>],
+    memory: &'a [Cell<u8>],
+    globals: &'a [Cell<Option<Object>>],
+    // Clones share the globals but not the heap, so only the process built
+    // with them treats them as collector roots.
+    owns_globals: bool,
+    heap: heap::Heap<'a>,
+}
+
+impl<'a> Clone for Process<'a> {
+    /// Copies the stacks, locals, registers and, with the `alloc` feature,
+    /// an allocated heap. The memory and globals the process was built with
+    /// are borrowed rather than copied, so writes to them from either process
+    /// are seen by both. A heap arena can't be copied, so the clone's heap
+    /// is empty; see `fork_into`.
+    fn clone(&self) -> Self {
+        self.copy_with(self.heap.clone())
+    }
+}
+
+impl<'a> Process<'a> {
+    fn copy_with<'b>(&self, heap: heap::Heap<'b>) -> Process<'b> where 'a: 'b {
+        let mut stack = Vec::new();
+        stack.extend(&self.stack);
+
+        let mut callstack = Vec::new();
+        callstack.extend(&self.callstack);
+
+        let mut locals = Vec::new();
+        locals.extend(&self.locals);
+
+        let mut scratch = Vec::new();
+        scratch.extend(&self.scratch);
+
+        Process {
+            ip: self.ip,
+            stack,
+            callstack,
+            locals,
+            code: self.code,
+            scratch,
+            intrinsics: self.intrinsics,
+            literals: self.literals,
+            strings: self.strings,
+            exports: self.exports,
+            memory: self.memory,
+            globals: self.globals,
+            owns_globals: false,
+            heap,
+        }
+    }
+
+    /// Copies the process like `clone`, but gives the copy its own heap in
+    /// `arena`, holding the objects this process's heap does. Fails with
+    /// `OutOfMemory` if they don't fit.
+    pub fn fork_into<'b>(&self, arena: &'b mut [Object]) -> Result<Process<'b>, HaltReason> where 'a: 'b {
+        let heap = self.heap.copy_into(Cell::from_mut(arena).as_slice_of_cells())?;
+        Ok(self.copy_with(heap))
+    }
 }
 
 impl From<StackUnderflow> for HaltReason {
@@ -181,22 +622,111 @@ impl From<PopFail> for HaltReason {
     }
 }
 
+/// A host function called by `Instruction::Intrinsic`. Returning an error
+/// halts the process with it.
+pub type IntrinsicFn = fn(&mut Process) -> Result<(), HaltReason>;
+
+/// How `Instruction::Intrinsic` is carried out.
+trait Dispatch {
+    fn intrinsic(&mut self, process: &mut Process, index: u64) -> Result<(), HaltReason>;
+}
+
+/// Calls into the host's intrinsic table.
+struct Host;
+
+impl Dispatch for Host {
+    fn intrinsic(&mut self, process: &mut Process, index: u64) -> Result<(), HaltReason> {
+        let func = match process.intrinsics.get(index as usize) {
+            Some(f) => f,
+            None => return Err(HaltReason::InvalidIntrinsic),
+        };
+
+        func(process)
+    }
+}
+
+/// A function made available to the host by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Export<'a> {
+    pub name: &'a str,
+    pub entry: usize,
+    pub arity: usize,
+    pub results: usize,
+}
+
+/// Everything an assembled program consists of, apart from the host's
+/// intrinsics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Program<'a> {
+    pub code: &'a [Instruction],
+    pub literals: &'a [Object],
+    pub strings: &'a [&'a [u8]],
+    pub exports: &'a [Export<'a>],
+}
+
+// The bits of a `width`-bit field starting `offset` bits up.
+fn field_mask(offset: u64, width: u64) -> u64 {
+    let mask = if width >= 64 { !0 } else { (1 << width) - 1 };
+    if offset >= 64 { 0 } else { mask << offset }
+}
+
+fn find_export<'a>(exports: &'a [Export<'a>], name: &str) -> Option<&'a Export<'a>> {
+    exports.iter().find(|export| export.name == name)
+}
+
 pub struct ProcessBuilder<'a> {
     code: &'a [Instruction],
-    intrinsics: &'a [fn(&mut Process)],
+    intrinsics: &'a [IntrinsicFn],
     literals: &'a [Object],
+    strings: &'a [&'a [u8]],
+    exports: &'a [Export<'a>],
+    memory: &'a [Cell<u8>],
+    globals: &'a [Cell<Option<Object>>],
+    heap: &'a [Cell<Object>],
+    heap_limit: usize,
+    registers: u8,
 }
 
+const DEFAULT_REGISTERS: u8 = 4;
+// In `Object`-sized words, so 1 MiB.
+const DEFAULT_HEAP_LIMIT: usize = 1 << 16;
+// Registers are stored inline in every `Process`, so keep the file small.
+const MAX_REGISTERS: u8 = 16;
+// How deeply `Eq`, `Neq` and `Compare` follow nested arrays and tuples.
+const MAX_COMPARE_DEPTH: usize = 32;
+
 impl<'a> ProcessBuilder<'a> {
+    pub fn from_program(program: &Program<'a>) -> ProcessBuilder<'a> {
+        ProcessBuilder {
+            code: program.code,
+            intrinsics: &[],
+            literals: program.literals,
+            strings: program.strings,
+            exports: program.exports,
+            memory: &[],
+            globals: &[],
+            heap: &[],
+            heap_limit: DEFAULT_HEAP_LIMIT,
+            registers: DEFAULT_REGISTERS,
+        }
+    }
+
     pub fn new(code: &'a [Instruction]) -> ProcessBuilder {
         ProcessBuilder {
             code,
             intrinsics: &[],
             literals: &[],
+            strings: &[],
+            exports: &[],
+            memory: &[],
+            globals: &[],
+            heap: &[],
+            heap_limit: DEFAULT_HEAP_LIMIT,
+            registers: DEFAULT_REGISTERS,
         }
     }
 
-    pub fn intrinsics(&mut self, intrinsics: &'a [fn(&mut Process)]) -> &mut Self {
+    pub fn intrinsics(&mut self, intrinsics: &'a [IntrinsicFn]) -> &mut Self {
         self.intrinsics = intrinsics;
         self
     }
@@ -206,39 +736,261 @@ impl<'a> ProcessBuilder<'a> {
         self
     }
 
+    pub fn strings(&mut self, strings: &'a [&'a [u8]]) -> &mut Self {
+        self.strings = strings;
+        self
+    }
+
+    pub fn exports(&mut self, exports: &'a [Export<'a>]) -> &mut Self {
+        self.exports = exports;
+        self
+    }
+
+    pub fn export(&self, name: &str) -> Option<&'a Export<'a>> {
+        find_export(self.exports, name)
+    }
+
+    /// Gives the process a byte-addressable memory for the `Load*` and
+    /// `Store*` instructions. Processes cloned from one another share it.
+    pub fn memory(&mut self, memory: &'a mut [u8]) -> &mut Self {
+        self.memory = Cell::from_mut(memory).as_slice_of_cells();
+        self
+    }
+
+    /// Gives the process a table of global slots for `LoadGlobal` and
+    /// `StoreGlobal`, holding their initial values. Processes cloned from one
+    /// another share it, but heap references in it belong to the heap of the
+    /// process built with it.
+    pub fn globals(&mut self, globals: &'a mut [Option<Object>]) -> &mut Self {
+        self.globals = Cell::from_mut(globals).as_slice_of_cells();
+        self
+    }
+
+    /// Gives the process an arena for compound objects. Without one, the
+    /// heap is empty unless the `alloc` feature is enabled, in which case it
+    /// is allocated and grown as needed.
+    ///
+    /// A clone of the process gets an empty heap rather than sharing the
+    /// arena; use `Process::fork_into` to copy the heap into another arena.
+    /// Allocated heaps are copied by `clone`.
+    pub fn heap(&mut self, arena: &'a mut [Object]) -> &mut Self {
+        self.heap = Cell::from_mut(arena).as_slice_of_cells();
+        self
+    }
+
+    /// Caps the size, in objects, that a heap allocated by the `alloc`
+    /// feature may grow to. Defaults to 65536.
+    pub fn heap_limit(&mut self, words: usize) -> &mut Self {
+        self.heap_limit = words;
+        self
+    }
+
+    /// Sets the size of the register file used by `StoreReg` and `LoadReg`.
+    /// Defaults to 4, and is capped at 16.
+    pub fn registers(&mut self, count: u8) -> &mut Self {
+        self.registers = cmp::min(count, MAX_REGISTERS);
+        self
+    }
+
     pub fn build(&self) -> Process<'a> {
+        let mut scratch = Vec::new();
+        scratch.extend((0..self.registers).map(|_| None));
+
         Process {
             ip: 0,
             stack: Vec::new(),
             callstack: Vec::new(),
+            locals: Vec::new(),
             code: self.code,
             intrinsics: self.intrinsics,
-            scratch: [None;4],
+            scratch,
             literals: self.literals,
+            strings: self.strings,
+            exports: self.exports,
+            memory: self.memory,
+            globals: self.globals,
+            owns_globals: true,
+            heap: heap::Heap::new(self.heap, self.heap_limit),
         }
     }
 }
 
 impl<'a> Process<'a> {
     pub fn new(code: &'a [Instruction]) -> Process<'a> {
-        Process {
-            ip: 0,
-            stack: Vec::new(),
-            callstack: Vec::new(),
-            code,
-            intrinsics: &[],
-            scratch: [None;4],
-            literals: &[],
-        }
+        ProcessBuilder::new(code).build()
     }
 
     pub fn run(&mut self, cycle_limit: u64) -> Result<!, HaltReason> {
         for _ in 0..cycle_limit {
-            self.__run_once()?;
+            self.__run_once(&mut Host)?;
+        }
+        Err(HaltReason::CycleLimit)
+    }
+
+    /// Runs like `run`, logging the stack effects and result of every
+    /// intrinsic call, including ones that fail.
+    ///
+    /// Only stack effects are logged. Intrinsics that write to memory or
+    /// globals won't have those writes reproduced by `run_replaying`, so the
+    /// replay will diverge from the recorded run.
+    pub fn run_recording<R: Recorder>(&mut self, cycle_limit: u64, recorder: &mut R) -> Result<!, HaltReason> {
+        let mut dispatch = replay::Recording(recorder);
+        for _ in 0..cycle_limit {
+            self.__run_once(&mut dispatch)?;
+        }
+        Err(HaltReason::CycleLimit)
+    }
+
+    /// Runs like `run`, but applies recorded intrinsic stack effects instead
+    /// of calling the intrinsics, halting where a recorded call failed.
+    pub fn run_replaying<R: Replayer>(&mut self, cycle_limit: u64, replayer: &mut R) -> Result<!, HaltReason> {
+        let mut dispatch = replay::Replaying(replayer);
+        for _ in 0..cycle_limit {
+            self.__run_once(&mut dispatch)?;
+        }
+        Err(HaltReason::CycleLimit)
+    }
+
+    /// Returns the process to its initial state, keeping the code, literals
+    /// and intrinsics it was built with.
+    pub fn reset(&mut self) {
+        self.ip = 0;
+        self.stack.clear();
+        self.callstack.clear();
+        self.locals.clear();
+        for slot in self.scratch.iter_mut() {
+            *slot = None;
+        }
+    }
+
+    /// Resets the process and pushes `args` in order, ready to run again.
+    pub fn reset_with_args(&mut self, args: &[Object]) -> Result<(), HaltReason> {
+        self.reset();
+        match self.stack.extend_from_slice(args) {
+            Ok(()) => Ok(()),
+            Err(()) => Err(HaltReason::StackOverflow),
+        }
+    }
+
+    /// Calls the function at `entry` with `args` pushed in order, running
+    /// until it returns with `Ret`. The values it leaves above the arguments'
+    /// base are returned, and stay on the stack until the next `reset`.
+    ///
+    /// If the call fails, the ip, call stack, locals and operand stack are
+    /// unwound to where they were before it, so the process can be called
+    /// again without a `reset`.
+    pub fn call(&mut self, entry: usize, args: &[Object], cycle_limit: u64) -> Result<&[Object], HaltReason> {
+        self.call_with(&mut Host, entry, args, cycle_limit)
+    }
+
+    /// Calls like `call`, logging the stack effects and result of every
+    /// intrinsic call, with the same limits as `run_recording`.
+    pub fn call_recording<R: Recorder>(&mut self, entry: usize, args: &[Object], cycle_limit: u64, recorder: &mut R) -> Result<&[Object], HaltReason> {
+        self.call_with(&mut replay::Recording(recorder), entry, args, cycle_limit)
+    }
+
+    /// Calls like `call`, but applies recorded intrinsic stack effects and
+    /// failures instead of calling the intrinsics.
+    pub fn call_replaying<R: Replayer>(&mut self, entry: usize, args: &[Object], cycle_limit: u64, replayer: &mut R) -> Result<&[Object], HaltReason> {
+        self.call_with(&mut replay::Replaying(replayer), entry, args, cycle_limit)
+    }
+
+    fn call_with<D: Dispatch>(&mut self, dispatch: &mut D, entry: usize, args: &[Object], cycle_limit: u64) -> Result<&[Object], HaltReason> {
+        let ip = self.ip;
+        let base = self.stack.len();
+        let depth = self.callstack.len();
+        let locals = self.locals.len();
+
+        let ret = self.run_call(dispatch, entry, args, cycle_limit, base, depth);
+
+        self.ip = ip;
+        match ret {
+            Ok(()) => Ok(&self.stack[base..]),
+            Err(reason) => {
+                self.callstack.truncate(depth);
+                self.locals.truncate(locals);
+                self.stack.truncate(base);
+                Err(reason)
+            }
+        }
+    }
+
+    fn run_call<D: Dispatch>(&mut self, dispatch: &mut D, entry: usize, args: &[Object], cycle_limit: u64, base: usize, depth: usize) -> Result<(), HaltReason> {
+        match self.stack.extend_from_slice(args) {
+            Ok(()) => {},
+            Err(()) => return Err(HaltReason::StackOverflow),
+        };
+        match self.callstack.push(StackFrame::new(self.ip, base, self.locals.len())) {
+            Ok(()) => {},
+            Err(_) => return Err(HaltReason::StackOverflow),
+        };
+        self.ip = entry;
+
+        for _ in 0..cycle_limit {
+            self.__run_once(dispatch)?;
+
+            if self.callstack.len() == depth {
+                if self.stack.len() < base {
+                    return Err(HaltReason::StackUnderflow);
+                }
+                return Ok(());
+            }
         }
         Err(HaltReason::CycleLimit)
     }
 
+    pub fn export(&self, name: &str) -> Option<&'a Export<'a>> {
+        find_export(self.exports, name)
+    }
+
+    /// The memory the process was built with, shared with its clones.
+    pub fn memory(&self) -> &'a [Cell<u8>] {
+        self.memory
+    }
+
+    pub fn global(&self, idx: u16) -> Option<Object> {
+        match self.globals.get(idx as usize) {
+            Some(slot) => slot.get(),
+            None => None,
+        }
+    }
+
+    /// Sets a global, returning false if there's no such slot. Like
+    /// `memory`, globals are shared with the process's clones.
+    pub fn set_global(&self, idx: u16, value: Object) -> bool {
+        match self.globals.get(idx as usize) {
+            Some(slot) => {
+                slot.set(Some(value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls an exported function by name, checking `args` and the values it
+    /// returns against its declared arity and result count. Like `call`, a
+    /// failed call, including one returning the wrong number of values, is
+    /// unwound.
+    pub fn call_export(&mut self, name: &str, args: &[Object], cycle_limit: u64) -> Result<&[Object], HaltReason> {
+        let export = match self.export(name) {
+            Some(export) => *export,
+            None => return Err(HaltReason::UnknownExport),
+        };
+
+        if args.len() != export.arity {
+            return Err(HaltReason::ArityMismatch);
+        }
+
+        let base = self.stack.len();
+        let results = self.call(export.entry, args, cycle_limit)?.len();
+        if results != export.results {
+            self.stack.truncate(base);
+            return Err(HaltReason::ArityMismatch);
+        }
+
+        Ok(&self.stack[base..])
+    }
+
     pub fn stack(&'a self) -> &'a [Object] {
         &self.stack
     }
@@ -272,6 +1024,39 @@ impl<'a> Process<'a> {
         }
     }
 
+    /// Reads typed arguments from the top of the stack without consuming them.
+    pub fn peek_args<T: FromObjects>(&self) -> Result<T, HaltReason> {
+        let len = self.stack.len();
+        if len < T::COUNT {
+            return Err(HaltReason::StackUnderflow);
+        }
+
+        T::from_objects(&self.stack[len - T::COUNT..])
+    }
+
+    /// Pops typed arguments from the top of the stack. On error the stack is
+    /// left untouched.
+    pub fn pop_args<T: FromObjects>(&mut self) -> Result<T, HaltReason> {
+        let args = self.peek_args()?;
+        let len = self.stack.len();
+        self.stack.truncate(len - T::COUNT);
+        Ok(args)
+    }
+
+    /// Pushes typed results onto the stack. Either every value is pushed or,
+    /// if there isn't room for all of them, none are.
+    pub fn push_results<T: IntoObjects>(&mut self, results: T) -> Result<(), HaltReason> {
+        if self.stack.capacity() - self.stack.len() < T::COUNT {
+            return Err(HaltReason::StackOverflow);
+        }
+
+        let stack = &mut self.stack;
+        results.into_objects(|obj| {
+            let _ = stack.push(obj);
+        });
+        Ok(())
+    }
+
     fn pop_as<T>(&mut self) -> Result<T, PopFail>
         where T: core::convert::TryFrom<Object> {
         match self.stack.pop() {
@@ -292,25 +1077,270 @@ impl<'a> Process<'a> {
         Ok((self.pop_as()?, self.pop_as()?))
     }
 
-    #[allow(dead_code)]
     fn pop3_as<T>(&mut self) -> Result<(T, T, T), PopFail>
         where T: core::convert::TryFrom<Object> {
         Ok((self.pop_as()?, self.pop_as()?, self.pop_as()?))
     }
 
-    #[inline(always)]
-    fn __run_once(&mut self) -> Result<(), HaltReason> {
-        use Instruction::*;
+    fn register(&mut self, idx: u8) -> Result<&mut Option<Object>, HaltReason> {
+        match self.scratch.get_mut(idx as usize) {
+            Some(slot) => Ok(slot),
+            None => Err(HaltReason::InvalidRegister),
+        }
+    }
 
+    fn store_reg(&mut self, idx: u8) -> Result<(), HaltReason> {
+        let obj = self.pop()?;
+        *self.register(idx)? = Some(obj);
+        Ok(())
+    }
 
-        let instruction = *match self.code.get(self.ip) {
-            Some(x) => x,
-            None => return Err(HaltReason::OutOfBounds),
+    fn load_reg(&mut self, idx: u8) -> Result<(), HaltReason> {
+        let obj = match *self.register(idx)? {
+            Some(obj) => obj,
+            None => return Err(HaltReason::EmptyScratch)
         };
 
-//        println!("{}: {:?}: {:?}: {:?}", self.ip, instruction, self.stack, self.scratch);
+        match self.stack.push(obj){
+            Ok(()) => Ok(()),
+            Err(_) => Err(HaltReason::StackOverflow),
+        }
+    }
 
-        match instruction {
+    /// Frees heap objects that are no longer reachable from the stack,
+    /// registers, locals or globals. Surviving objects may move, and handles
+    /// held anywhere else become stale.
+    ///
+    /// A clone's globals are shared with the process it was cloned from, and
+    /// refer to that process's heap, so they aren't roots for the clone.
+    pub fn collect_garbage(&mut self) {
+        let globals = if self.owns_globals { self.globals } else { &[] };
+        let Process { ref mut heap, ref mut stack, ref mut scratch, ref mut locals, .. } = *self;
+
+        heap.collect(|visit| {
+            for obj in stack.iter_mut() {
+                visit(obj);
+            }
+            for slot in scratch.iter_mut().chain(locals.iter_mut()) {
+                if let Some(ref mut obj) = *slot {
+                    visit(obj);
+                }
+            }
+            for slot in globals {
+                if let Some(mut obj) = slot.get() {
+                    visit(&mut obj);
+                    slot.set(Some(obj));
+                }
+            }
+        });
+    }
+
+    // Makes room for `words` words on the heap, collecting and then growing
+    // it if need be.
+    fn reserve(&mut self, words: usize) -> Result<(), HaltReason> {
+        if self.heap.fits(words) {
+            return Ok(());
+        }
+
+        self.collect_garbage();
+        if self.heap.wants_growth(words) {
+            self.heap.grow(words);
+        }
+
+        if self.heap.fits(words) {
+            Ok(())
+        } else {
+            Err(HaltReason::OutOfMemory)
+        }
+    }
+
+    // Room is made while the items are still on the stack, so a collection
+    // keeps them alive and updates them.
+    fn make_items(&mut self, kind: heap::Kind, n: u8) -> Result<(), HaltReason> {
+        let len = self.stack.len();
+        let n = n as usize;
+        if len < n {
+            return Err(HaltReason::StackUnderflow);
+        }
+
+        self.reserve(heap::words_for(kind, n))?;
+        let obj = self.heap.alloc_items(kind, &self.stack[len - n..]);
+        self.stack.truncate(len - n);
+        self.push_new(obj)
+    }
+
+    // The order of kinds of object for `Compare`. Literal and heap strings
+    // share a rank so they compare by content.
+    fn rank(&self, obj: Object) -> Result<u8, HaltReason> {
+        Ok(match obj {
+            Object::Unsigned(_) => 0,
+            Object::Signed(_) => 1,
+            Object::Bool(_) => 2,
+            Object::Ref(r) => match self.heap.kind(r)? {
+                heap::Kind::Array => 3,
+                heap::Kind::Tuple => 4,
+                heap::Kind::Bytes => 5,
+            },
+            Object::Str(_) => 5,
+            Object::Nil => 6,
+        })
+    }
+
+    fn compare_objects(&self, x: Object, y: Object, depth: usize) -> Result<cmp::Ordering, HaltReason> {
+        if x == y {
+            return Ok(cmp::Ordering::Equal);
+        }
+        if depth >= MAX_COMPARE_DEPTH {
+            return Err(HaltReason::StackOverflow);
+        }
+
+        match self.rank(x)?.cmp(&self.rank(y)?) {
+            cmp::Ordering::Equal => {},
+            ordering => return Ok(ordering),
+        }
+
+        match (x, y) {
+            (Object::Ref(a), Object::Ref(b)) if self.heap.kind(a)? != heap::Kind::Bytes => {
+                let (len_a, len_b) = (self.heap.len(a)?, self.heap.len(b)?);
+                for i in 0..cmp::min(len_a, len_b) as u64 {
+                    let (x, y) = (self.heap.index(a, i)?, self.heap.index(b, i)?);
+                    match self.compare_objects(x, y, depth + 1)? {
+                        cmp::Ordering::Equal => {},
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Ok(len_a.cmp(&len_b))
+            }
+            (Object::Ref(_), _) | (Object::Str(_), _) => self.compare_strings(x, y),
+            _ => Ok(x.cmp(&y)),
+        }
+    }
+
+    fn push_wide_unsigned(&mut self, x: u128) -> Result<(), HaltReason> {
+        match self.stack.extend_from_slice(&[Object::Unsigned((x >> 64) as u64), Object::Unsigned(x as u64)]) {
+            Ok(()) => Ok(()),
+            Err(()) => Err(HaltReason::StackOverflow),
+        }
+    }
+
+    fn push_wide_signed(&mut self, x: i128) -> Result<(), HaltReason> {
+        match self.stack.extend_from_slice(&[Object::Signed((x >> 64) as i64), Object::Unsigned(x as u64)]) {
+            Ok(()) => Ok(()),
+            Err(()) => Err(HaltReason::StackOverflow),
+        }
+    }
+
+    fn push_new(&mut self, obj: Object) -> Result<(), HaltReason> {
+        match self.stack.push(obj){
+            Ok(()) => Ok(()),
+            Err(_) => Err(HaltReason::StackOverflow),
+        }
+    }
+
+    fn memory_range(&self, addr: u64, size: usize) -> Result<&'a [Cell<u8>], HaltReason> {
+        let start = addr as usize;
+        match start.checked_add(size) {
+            Some(end) if addr <= usize::max_value() as u64 && end <= self.memory.len() => {
+                Ok(&self.memory[start..end])
+            }
+            _ => Err(HaltReason::MemoryOutOfBounds),
+        }
+    }
+
+    // Memory is little-endian; values are truncated to the access size.
+    fn load(&mut self, size: usize) -> Result<(), HaltReason> {
+        let addr = self.pop_as::<u64>()?;
+        let bytes = self.memory_range(addr, size)?;
+
+        let mut x = 0u64;
+        for (i, byte) in bytes.iter().enumerate() {
+            x |= u64::from(byte.get()) << (8 * i);
+        }
+
+        match self.stack.push(Object::Unsigned(x)){
+            Ok(()) => Ok(()),
+            Err(_) => Err(HaltReason::StackOverflow),
+        }
+    }
+
+    fn store(&mut self, size: usize) -> Result<(), HaltReason> {
+        let (x, addr) = self.pop2_as::<u64>()?;
+        let bytes = self.memory_range(addr, size)?;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            byte.set((x >> (8 * i)) as u8);
+        }
+
+        Ok(())
+    }
+
+    fn table_entry(&self, idx: usize) -> Result<u64, HaltReason> {
+        match self.literals.get(idx) {
+            Some(Object::Unsigned(x)) => Ok(*x),
+            _ => Err(HaltReason::InvalidSwitchTable),
+        }
+    }
+
+    fn call_to(&mut self, target: usize) -> Result<(), HaltReason> {
+        let sf = StackFrame::new(self.ip, self.stack.len(), self.locals.len());
+        match self.callstack.push(sf){
+            Ok(()) => {},
+            Err(_) => return Err(HaltReason::StackOverflow),
+        };
+        self.ip = target;
+        Ok(())
+    }
+
+    fn relative_target(&self, offset: i64) -> Result<usize, HaltReason> {
+        match (self.ip as i64).checked_add(offset) {
+            Some(x) if x >= 0 => Ok(x as usize),
+            _ => Err(HaltReason::OutOfBounds),
+        }
+    }
+
+    fn frame(&self) -> Result<&StackFrame, HaltReason> {
+        match self.callstack.last() {
+            Some(sf) => Ok(sf),
+            None => Err(HaltReason::NoFrame),
+        }
+    }
+
+    fn arg_index(&self, n: u8) -> Result<usize, HaltReason> {
+        let frame = self.frame()?;
+        let n = n as usize;
+
+        // An argument may already have been popped by the function itself.
+        if n >= frame.args || frame.base + n >= self.stack.len() {
+            return Err(HaltReason::InvalidLocal);
+        }
+
+        Ok(frame.base + n)
+    }
+
+    fn local_index(&self, n: u8) -> Result<usize, HaltReason> {
+        let frame = self.frame()?;
+        let idx = frame.locals + n as usize;
+
+        if idx >= self.locals.len() {
+            return Err(HaltReason::InvalidLocal);
+        }
+
+        Ok(idx)
+    }
+
+    #[inline(always)]
+    fn __run_once<D: Dispatch>(&mut self, dispatch: &mut D) -> Result<(), HaltReason> {
+        use Instruction::*;
+
+
+        let instruction = *match self.code.get(self.ip) {
+            Some(x) => x,
+            None => return Err(HaltReason::OutOfBounds),
+        };
+
+//        println!("{}: {:?}: {:?}: {:?}", self.ip, instruction, self.stack, self.scratch);
+
+        match instruction {
             LiteralUnsigned(x) => match self.stack.push(Object::Unsigned(x.into())) {
                 Ok(()) => {},
                 Err(_) => return Err(HaltReason::StackOverflow),
@@ -323,6 +1353,17 @@ impl<'a> Process<'a> {
                 Ok(()) => {},
                 Err(_) => return Err(HaltReason::StackOverflow),
             },
+            LiteralNil => match self.stack.push(Object::Nil) {
+                Ok(()) => {},
+                Err(_) => return Err(HaltReason::StackOverflow),
+            },
+            IsNil => {
+                let x = self.pop()?;
+                match self.stack.push(Object::Bool(x == Object::Nil)) {
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
             LiteralIndexed(x) => {
                 let literal = match self.literals.get(x as usize) {
                     Some(f) => f,
@@ -404,6 +1445,151 @@ impl<'a> Process<'a> {
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             },
+            MinUnsigned => {
+                let (y, x) = self.pop2_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(cmp::min(x, y))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            MaxUnsigned => {
+                let (y, x) = self.pop2_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(cmp::max(x, y))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            ClampUnsigned => {
+                let (high, low, x) = self.pop3_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(cmp::max(low, cmp::min(x, high)))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            AbsUnsigned => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            NegateUnsigned => {
+                let x = match self.pop_as::<u64>()?.checked_neg() {
+                    Some(x) => x,
+                    None => return Err(HaltReason::Overflow),
+                };
+                match self.stack.push(Object::Unsigned(x)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            MinSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                match self.stack.push(Object::Signed(cmp::min(x, y))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            MaxSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                match self.stack.push(Object::Signed(cmp::max(x, y))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            ClampSigned => {
+                let (high, low, x) = self.pop3_as::<i64>()?;
+                match self.stack.push(Object::Signed(cmp::max(low, cmp::min(x, high)))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            AbsSigned => {
+                let x = match self.pop_as::<i64>()?.checked_abs() {
+                    Some(x) => x,
+                    None => return Err(HaltReason::Overflow),
+                };
+                match self.stack.push(Object::Signed(x)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            NegateSigned => {
+                let x = match self.pop_as::<i64>()?.checked_neg() {
+                    Some(x) => x,
+                    None => return Err(HaltReason::Overflow),
+                };
+                match self.stack.push(Object::Signed(x)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            WideMultiplyUnsigned => {
+                let (y, x) = self.pop2_as::<u64>()?;
+                let wide = u128::from(x) * u128::from(y);
+                self.push_wide_unsigned(wide)?;
+            }
+            WideMultiplySigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                let wide = i128::from(x) * i128::from(y);
+                self.push_wide_signed(wide)?;
+            }
+            MulHiUnsigned => {
+                let (y, x) = self.pop2_as::<u64>()?;
+                let wide = u128::from(x) * u128::from(y);
+                match self.stack.push(Object::Unsigned((wide >> 64) as u64)) {
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            MulHiSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                let wide = i128::from(x) * i128::from(y);
+                match self.stack.push(Object::Signed((wide >> 64) as i64)) {
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            WideDivideUnsigned => {
+                let divisor = self.pop_as::<u64>()?;
+                let (low, high) = self.pop2_as::<u64>()?;
+                if divisor == 0 {
+                    return Err(HaltReason::DivisionByZero);
+                }
+
+                let wide = u128::from(high) << 64 | u128::from(low);
+                let quotient = wide / u128::from(divisor);
+                if quotient > u128::from(u64::max_value()) {
+                    return Err(HaltReason::Overflow);
+                }
+
+                let remainder = wide % u128::from(divisor);
+
+                match self.stack.extend_from_slice(&[Object::Unsigned(quotient as u64), Object::Unsigned(remainder as u64)]) {
+                    Ok(()) => {},
+                    Err(()) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            WideDivideSigned => {
+                let divisor = self.pop_as::<i64>()?;
+                let low = self.pop_as::<u64>()?;
+                let high = self.pop_as::<i64>()?;
+                if divisor == 0 {
+                    return Err(HaltReason::DivisionByZero);
+                }
+
+                let wide = i128::from(high) << 64 | i128::from(low);
+                let quotient = match wide.checked_div(i128::from(divisor)) {
+                    Some(x) if x >= i128::from(i64::min_value()) && x <= i128::from(i64::max_value()) => x,
+                    _ => return Err(HaltReason::Overflow),
+                };
+                let remainder = wide % i128::from(divisor);
+
+                match self.stack.extend_from_slice(&[Object::Signed(quotient as i64), Object::Signed(remainder as i64)]) {
+                    Ok(()) => {},
+                    Err(()) => return Err(HaltReason::StackOverflow),
+                };
+            }
             BitAnd => {
                 let (y, x) = self.pop2_as::<u64>()?;
                 match self.stack.push(Object::Unsigned(x & y)){
@@ -460,6 +1646,126 @@ impl<'a> Process<'a> {
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             }
+            PopCount => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x.count_ones().into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            LeadingZeros => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x.leading_zeros().into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            TrailingZeros => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x.trailing_zeros().into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            ByteSwap => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x.swap_bytes())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitReverse => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Unsigned(x.reverse_bits())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitExtract => {
+                let (width, offset) = self.pop2_as::<u64>()?;
+                let x = self.pop_as::<u64>()?;
+                let field = if offset >= 64 { 0 } else { (x & field_mask(offset, width)) >> offset };
+                match self.stack.push(Object::Unsigned(field)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitInsert => {
+                let (width, offset) = self.pop2_as::<u64>()?;
+                let (field, x) = self.pop2_as::<u64>()?;
+                let mask = field_mask(offset, width);
+                let field = if offset >= 64 { 0 } else { field << offset };
+                match self.stack.push(Object::Unsigned(x & !mask | field & mask)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitAndSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                match self.stack.push(Object::Signed(x & y)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitOrSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                match self.stack.push(Object::Signed(x | y)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitNotSigned => {
+                let x = self.pop_as::<i64>()?;
+                match self.stack.push(Object::Signed(!x)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitXorSigned => {
+                let (y, x) = self.pop2_as::<i64>()?;
+                match self.stack.push(Object::Signed(x ^ y)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitLShiftSigned => {
+                let y = self.pop_as::<u64>()?;
+                let x = self.pop_as::<i64>()?;
+                let shifted = if y >= 64 { 0 } else { x << y };
+                match self.stack.push(Object::Signed(shifted)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            BitRShiftSigned => {
+                let y = self.pop_as::<u64>()?;
+                let x = self.pop_as::<i64>()?;
+                match self.stack.push(Object::Signed(x >> cmp::min(y, 63))){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            SignExtend8 => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Signed((x as i8).into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            SignExtend16 => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Signed((x as i16).into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            SignExtend32 => {
+                let x = self.pop_as::<u64>()?;
+                match self.stack.push(Object::Signed((x as i32).into())){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
             LogAnd => {
                 let (y, x) = self.pop2_as::<bool>()?;
                 match self.stack.push(Object::Bool(x & y)){
@@ -572,6 +1878,33 @@ impl<'a> Process<'a> {
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             }
+            Eq => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let equal = self.compare_objects(x, y, 0)? == cmp::Ordering::Equal;
+                match self.stack.push(Object::Bool(equal)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            Neq => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let equal = self.compare_objects(x, y, 0)? == cmp::Ordering::Equal;
+                match self.stack.push(Object::Bool(!equal)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
+            Compare => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                let ordering = self.compare_objects(x, y, 0)?;
+                match self.stack.push(Object::Signed(ordering as i64)){
+                    Ok(()) => {},
+                    Err(_) => return Err(HaltReason::StackOverflow),
+                };
+            }
             Branch => {
                 let x = self.pop_as::<u64>()?;
                 self.ip = x as usize;
@@ -586,91 +1919,287 @@ impl<'a> Process<'a> {
                     return Ok(());
                 }
             }
-            Call => {
-                let sf = StackFrame {
-                    ip: self.ip
-                };
-                match self.callstack.push(sf){
+            BranchFalse => {
+                let x = self.pop_as::<u64>()?;
+
+                let y = self.pop_as::<bool>()?;
+                if !y {
+                    self.ip = x as usize;
+                    return Ok(());
+                }
+            }
+            Select => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let cond = self.pop_as::<bool>()?;
+                match self.stack.push(if cond { a } else { b }){
                     Ok(()) => {},
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
+            }
+            Call => {
                 let x = self.pop_as::<u64>()?;
-                self.ip = x as usize;
+                return self.call_to(x as usize);
+            }
+            BranchRelative => {
+                let x = self.pop_as::<i64>()?;
+                self.ip = self.relative_target(x)?;
                 return Ok(());
             }
-            Ret => {
-                let stack_frame = match self.callstack.pop() {
-                    Some(sf) => sf,
-                    None => return Err(HaltReason::StackUnderflow),
-                };
+            BranchTrueRelative => {
+                let x = self.pop_as::<i64>()?;
 
-                self.ip = stack_frame.ip;
-                // Don't return here, we want to increment the instruction pointer.
+                let y = self.pop_as::<bool>()?;
+                if y {
+                    self.ip = self.relative_target(x)?;
+                    return Ok(());
+                }
             }
-            PushSlot1 => {
-                self.scratch[0] = Some(self.pop()?);
+            CallRelative => {
+                let x = self.pop_as::<i64>()?;
+                let target = self.relative_target(x)?;
+                return self.call_to(target);
             }
-            PushSlot2 => {
-                self.scratch[1] = Some(self.pop()?);
+            Jump(x) => {
+                self.ip = x as usize;
+                return Ok(());
             }
-            PushSlot3 => {
-                self.scratch[2] = Some(self.pop()?);
+            JumpIfTrue(x) => {
+                if self.pop_as::<bool>()? {
+                    self.ip = x as usize;
+                    return Ok(());
+                }
             }
-            PushSlot4 => {
-                self.scratch[3] = Some(self.pop()?);
+            JumpIfFalse(x) => {
+                if !self.pop_as::<bool>()? {
+                    self.ip = x as usize;
+                    return Ok(());
+                }
+            }
+            CallImm(x) => {
+                return self.call_to(x as usize);
             }
-            PopSlot1 => {
-                let obj = match self.scratch[0] {
+            LoadGlobal(x) => {
+                let obj = match self.globals.get(x as usize) {
+                    Some(slot) => slot.get(),
+                    None => return Err(HaltReason::InvalidGlobal),
+                };
+                let obj = match obj {
                     Some(obj) => obj,
-                    None => return Err(HaltReason::EmptyScratch)
+                    None => return Err(HaltReason::EmptyGlobal),
                 };
-                
+
                 match self.stack.push(obj){
                     Ok(()) => {},
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             }
-            PopSlot2 => {
-                let obj = match self.scratch[1] {
-                    Some(obj) => obj,
-                    None => return Err(HaltReason::EmptyScratch)
+            StoreGlobal(x) => {
+                let obj = self.pop()?;
+                match self.globals.get(x as usize) {
+                    Some(slot) => slot.set(Some(obj)),
+                    None => return Err(HaltReason::InvalidGlobal),
                 };
-                
-                match self.stack.push(obj){
+            }
+            MakeArray(n) => self.make_items(heap::Kind::Array, n)?,
+            MakeTuple(n) => self.make_items(heap::Kind::Tuple, n)?,
+            MakeBytes => {
+                let len = self.pop_as::<u64>()?;
+                if len > u64::from(u32::max_value()) {
+                    return Err(HaltReason::OutOfMemory);
+                }
+
+                self.reserve(heap::words_for(heap::Kind::Bytes, len as usize))?;
+                let bytes = self.heap.alloc(heap::Kind::Bytes, len as usize);
+                self.push_new(bytes)?;
+            }
+            Length => {
+                let r = self.pop_as::<HeapRef>()?;
+                let len = self.heap.len(r)?;
+                self.push_new(Object::Unsigned(len as u64))?;
+            }
+            Index => {
+                let idx = self.pop_as::<u64>()?;
+                let r = self.pop_as::<HeapRef>()?;
+                let item = self.heap.index(r, idx)?;
+                self.push_new(item)?;
+            }
+            SetIndex => {
+                let value = self.pop()?;
+                let idx = self.pop_as::<u64>()?;
+                let r = self.pop_as::<HeapRef>()?;
+                self.heap.set_index(r, idx, value)?;
+            }
+            LiteralStr(x) => self.literal_str(x)?,
+            StrLength => self.str_length()?,
+            StrByte => self.str_byte()?,
+            StrSlice => self.str_slice()?,
+            StrCompare => self.str_compare()?,
+            StrConcat => self.str_concat()?,
+            FormatUnsigned => self.format_unsigned()?,
+            FormatSigned => self.format_signed()?,
+            ParseUnsigned => self.parse_unsigned()?,
+            ParseSigned => self.parse_signed()?,
+            LoadU8 => self.load(1)?,
+            LoadU16 => self.load(2)?,
+            LoadU32 => self.load(4)?,
+            LoadU64 => self.load(8)?,
+            StoreU8 => self.store(1)?,
+            StoreU16 => self.store(2)?,
+            StoreU32 => self.store(4)?,
+            StoreU64 => self.store(8)?,
+            Switch(x) => {
+                let selector = self.pop_as::<u64>()?;
+                let table = x as usize;
+
+                let len = self.table_entry(table)?;
+
+                // Check the table fits in the literal pool, so a bad length is
+                // caught whichever way the selector goes. Entries themselves
+                // are only checked when they're selected.
+                match (table as u64).checked_add(1).and_then(|x| x.checked_add(len)) {
+                    Some(last) if last < self.literals.len() as u64 => {},
+                    _ => return Err(HaltReason::InvalidSwitchTable),
+                };
+
+                let entry = if selector < len {
+                    table + 2 + selector as usize
+                } else {
+                    table + 1
+                };
+
+                self.ip = self.table_entry(entry)? as usize;
+                return Ok(());
+            }
+            Ret => {
+                let stack_frame = match self.callstack.pop() {
+                    Some(sf) => sf,
+                    None => return Err(HaltReason::StackUnderflow),
+                };
+
+                if let Some(results) = stack_frame.results {
+                    let len = self.stack.len();
+                    if len < stack_frame.base + results {
+                        return Err(HaltReason::StackUnderflow);
+                    }
+
+                    for i in 0..results {
+                        self.stack[stack_frame.base + i] = self.stack[len - results + i];
+                    }
+                    self.stack.truncate(stack_frame.base + results);
+                }
+                self.locals.truncate(stack_frame.locals);
+
+                self.ip = stack_frame.ip;
+                // Don't return here, we want to increment the instruction pointer.
+            }
+            TailCall(args) => {
+                let x = self.pop_as::<u64>()?;
+                let args = args as usize;
+                let len = self.stack.len();
+
+                let frame = match self.callstack.pop() {
+                    Some(sf) => sf,
+                    None => return Err(HaltReason::NoFrame),
+                };
+
+                if len < args {
+                    return Err(HaltReason::StackUnderflow);
+                }
+
+                let start = len - args;
+                if start > frame.base {
+                    for i in 0..args {
+                        self.stack[frame.base + i] = self.stack[start + i];
+                    }
+                    self.stack.truncate(frame.base + args);
+                }
+                self.locals.truncate(frame.locals);
+
+                let sf = StackFrame::new(frame.ip, self.stack.len(), frame.locals);
+                match self.callstack.push(sf){
                     Ok(()) => {},
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
+                self.ip = x as usize;
+                return Ok(());
             }
-            PopSlot3 => {
-                let obj = match self.scratch[2] {
-                    Some(obj) => obj,
-                    None => return Err(HaltReason::EmptyScratch)
+            Enter(args, locals, results) => {
+                let len = self.stack.len();
+                let frame = match self.callstack.last_mut() {
+                    Some(sf) => sf,
+                    None => return Err(HaltReason::NoFrame),
                 };
-                
+
+                if len < args as usize {
+                    return Err(HaltReason::StackUnderflow);
+                }
+
+                frame.base = len - args as usize;
+                frame.args = args as usize;
+                frame.results = Some(results as usize);
+
+                for _ in 0..locals {
+                    match self.locals.push(None) {
+                        Ok(()) => {},
+                        Err(_) => return Err(HaltReason::StackOverflow),
+                    };
+                }
+            }
+            LoadArg(x) => {
+                let idx = self.arg_index(x)?;
+                let obj = self.stack[idx];
+
                 match self.stack.push(obj){
                     Ok(()) => {},
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             }
-            PopSlot4 => {
-                let obj = match self.scratch[3] {
+            StoreArg(x) => {
+                // Pop first, as the argument itself may be the top value.
+                let obj = self.pop()?;
+                let idx = self.arg_index(x)?;
+                self.stack[idx] = obj;
+            }
+            LoadLocal(x) => {
+                let idx = self.local_index(x)?;
+                let obj = match self.locals[idx] {
                     Some(obj) => obj,
-                    None => return Err(HaltReason::EmptyScratch)
+                    None => return Err(HaltReason::EmptyLocal)
                 };
-                
+
                 match self.stack.push(obj){
                     Ok(()) => {},
                     Err(_) => return Err(HaltReason::StackOverflow),
                 };
             }
+            StoreLocal(x) => {
+                let idx = self.local_index(x)?;
+                self.locals[idx] = Some(self.pop()?);
+            }
+            PushSlot1 => self.store_reg(0)?,
+            PushSlot2 => self.store_reg(1)?,
+            PushSlot3 => self.store_reg(2)?,
+            PushSlot4 => self.store_reg(3)?,
+            PopSlot1 => self.load_reg(0)?,
+            PopSlot2 => self.load_reg(1)?,
+            PopSlot3 => self.load_reg(2)?,
+            PopSlot4 => self.load_reg(3)?,
+            StoreReg(x) => self.store_reg(x)?,
+            StoreRegKeep(x) => {
+                let obj = match self.stack.last() {
+                    Some(obj) => *obj,
+                    None => return Err(HaltReason::StackUnderflow),
+                };
+                *self.register(x)? = Some(obj);
+            }
+            LoadReg(x) => self.load_reg(x)?,
+            ClearReg(x) => {
+                *self.register(x)? = None;
+            }
             Intrinsic => {
                 let idx = self.pop_as::<u64>()?;
-                let func = match self.intrinsics.get(idx as usize) {
-                    Some(f) => f,
-                    None => return Err(HaltReason::InvalidIntrinsic),
-                };
-
-                func(self);
+                dispatch.intrinsic(self, idx)?;
             }
         }
         self.ip += 1;
@@ -0,0 +1,201 @@
+use heapless::Vec;
+use heapless::consts::*;
+
+use snapshot::{Reader, SnapshotError, Writer};
+use {Dispatch, HaltReason, Host, Object, Process};
+
+/// The observable effect of one `Intrinsic` instruction: which intrinsic ran,
+/// how many objects it removed from the stack, what it pushed afterwards and
+/// whether it failed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IntrinsicCall {
+    pub index: u64,
+    pub popped: usize,
+    pub pushed: Vec<Object, U32>,
+    pub result: Result<(), HaltReason>,
+}
+
+pub trait Recorder {
+    /// Returns false if the call could not be stored.
+    fn record(&mut self, call: &IntrinsicCall) -> bool;
+}
+
+pub trait Replayer {
+    /// Returns the next recorded call, or `None` once the recording is
+    /// exhausted.
+    fn next_call(&mut self) -> Option<IntrinsicCall>;
+}
+
+pub(crate) struct Recording<'r, R: 'r>(pub(crate) &'r mut R);
+
+impl<'r, R: Recorder> Dispatch for Recording<'r, R> {
+    fn intrinsic(&mut self, process: &mut Process, index: u64) -> Result<(), HaltReason> {
+        let mut before: Vec<Object, U32> = Vec::new();
+        before.extend(&process.stack);
+
+        let result = Host.intrinsic(process, index);
+
+        let kept = before.iter()
+            .zip(process.stack.iter())
+            .take_while(|&(x, y)| x == y)
+            .count();
+
+        let mut pushed = Vec::new();
+        pushed.extend(&process.stack[kept..]);
+
+        let call = IntrinsicCall {
+            index,
+            popped: before.len() - kept,
+            pushed,
+            result,
+        };
+
+        if self.0.record(&call) {
+            result
+        } else {
+            Err(HaltReason::RecordingFull)
+        }
+    }
+}
+
+pub(crate) struct Replaying<'r, R: 'r>(pub(crate) &'r mut R);
+
+impl<'r, R: Replayer> Dispatch for Replaying<'r, R> {
+    fn intrinsic(&mut self, process: &mut Process, index: u64) -> Result<(), HaltReason> {
+        let call = match self.0.next_call() {
+            Some(call) => call,
+            None => return Err(HaltReason::ReplayDivergence),
+        };
+
+        if call.index != index || call.popped > process.stack.len() {
+            return Err(HaltReason::ReplayDivergence);
+        }
+
+        let len = process.stack.len();
+        process.stack.truncate(len - call.popped);
+
+        for obj in &call.pushed {
+            match process.stack.push(*obj) {
+                Ok(()) => {},
+                Err(_) => return Err(HaltReason::StackOverflow),
+            };
+        }
+
+        call.result
+    }
+}
+
+// Failed calls are logged by their reason's position in this table, plus one
+// so that zero can stand for success.
+const REASONS: &[HaltReason] = &[
+    HaltReason::CycleLimit,
+    HaltReason::OutOfBounds,
+    HaltReason::StackUnderflow,
+    HaltReason::StackOverflow,
+    HaltReason::EmptyScratch,
+    HaltReason::TypeError,
+    HaltReason::InvalidIntrinsic,
+    HaltReason::InvalidLiteral,
+    HaltReason::RecordingFull,
+    HaltReason::ReplayDivergence,
+    HaltReason::UnknownExport,
+    HaltReason::ArityMismatch,
+    HaltReason::NoFrame,
+    HaltReason::InvalidLocal,
+    HaltReason::EmptyLocal,
+    HaltReason::InvalidSwitchTable,
+    HaltReason::MemoryOutOfBounds,
+    HaltReason::InvalidGlobal,
+    HaltReason::EmptyGlobal,
+    HaltReason::InvalidRegister,
+    HaltReason::InvalidReference,
+    HaltReason::IndexOutOfBounds,
+    HaltReason::OutOfMemory,
+    HaltReason::DivisionByZero,
+    HaltReason::Overflow,
+];
+
+/// Records intrinsic calls into a caller-provided byte buffer.
+pub struct LogRecorder<'b> {
+    w: Writer<'b>,
+}
+
+impl<'b> LogRecorder<'b> {
+    pub fn new(buf: &'b mut [u8]) -> LogRecorder<'b> {
+        LogRecorder {
+            w: Writer { buf, pos: 0 },
+        }
+    }
+
+    /// The recorded log, suitable for passing to `LogReplayer::new`.
+    pub fn log(&self) -> &[u8] {
+        &self.w.buf[..self.w.pos]
+    }
+
+    fn write_call(&mut self, call: &IntrinsicCall) -> Result<(), SnapshotError> {
+        self.w.u64(call.index)?;
+        self.w.u8(call.popped as u8)?;
+        self.w.u8(call.pushed.len() as u8)?;
+        for obj in &call.pushed {
+            self.w.object(Some(*obj))?;
+        }
+        match call.result {
+            Ok(()) => self.w.u8(0),
+            Err(reason) => match REASONS.iter().position(|x| *x == reason) {
+                Some(code) => self.w.u8(code as u8 + 1),
+                None => Err(SnapshotError::Unsupported),
+            },
+        }
+    }
+}
+
+impl<'b> Recorder for LogRecorder<'b> {
+    fn record(&mut self, call: &IntrinsicCall) -> bool {
+        let start = self.w.pos;
+
+        match self.write_call(call) {
+            Ok(()) => true,
+            Err(_) => {
+                self.w.pos = start;
+                false
+            }
+        }
+    }
+}
+
+/// Replays intrinsic calls from a log written by `LogRecorder`.
+pub struct LogReplayer<'b> {
+    r: Reader<'b>,
+}
+
+impl<'b> LogReplayer<'b> {
+    pub fn new(log: &'b [u8]) -> LogReplayer<'b> {
+        LogReplayer {
+            r: Reader { buf: log, pos: 0 },
+        }
+    }
+}
+
+impl<'b> Replayer for LogReplayer<'b> {
+    fn next_call(&mut self) -> Option<IntrinsicCall> {
+        let index = self.r.u64().ok()?;
+        let popped = self.r.u8().ok()? as usize;
+
+        let mut pushed = Vec::new();
+        for _ in 0..self.r.u8().ok()? {
+            pushed.push(self.r.object().ok()??).ok()?;
+        }
+
+        let result = match self.r.u8().ok()? {
+            0 => Ok(()),
+            code => Err(*REASONS.get(code as usize - 1)?),
+        };
+
+        Some(IntrinsicCall {
+            index,
+            popped,
+            pushed,
+            result,
+        })
+    }
+}
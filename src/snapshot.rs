@@ -0,0 +1,290 @@
+use core::hash::{Hash, Hasher};
+
+use heapless::Vec;
+
+use {Object, Process, StackFrame, StrRef};
+
+const MAGIC: &[u8; 4] = b"RLSS";
+const VERSION: u8 = 2;
+
+const TAG_NONE: u8 = 0;
+const TAG_UNSIGNED: u8 = 1;
+const TAG_SIGNED: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_STR: u8 = 4;
+const TAG_NIL: u8 = 5;
+
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[fail(display = "Snapshot Buffer Too Small")]
+    BufferTooSmall,
+    #[fail(display = "Malformed Snapshot")]
+    Malformed,
+    #[fail(display = "Unsupported Snapshot Version")]
+    UnsupportedVersion,
+    #[fail(display = "Snapshot Taken Against Different Code")]
+    CodeMismatch,
+    #[fail(display = "Process State Can't Be Snapshotted")]
+    Unsupported,
+}
+
+/// 64-bit FNV-1a, used to fingerprint the code and literals a snapshot
+/// belongs to.
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+pub(crate) struct Writer<'b> {
+    pub(crate) buf: &'b mut [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'b> Writer<'b> {
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let end = self.pos + bytes.len();
+        if end > self.buf.len() {
+            return Err(SnapshotError::BufferTooSmall);
+        }
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub(crate) fn u8(&mut self, x: u8) -> Result<(), SnapshotError> {
+        self.bytes(&[x])
+    }
+
+    pub(crate) fn u16(&mut self, x: u16) -> Result<(), SnapshotError> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    pub(crate) fn u64(&mut self, x: u64) -> Result<(), SnapshotError> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    pub(crate) fn object(&mut self, obj: Option<Object>) -> Result<(), SnapshotError> {
+        match obj {
+            None => self.u8(TAG_NONE),
+            Some(Object::Unsigned(x)) => {
+                self.u8(TAG_UNSIGNED)?;
+                self.u64(x)
+            }
+            Some(Object::Signed(x)) => {
+                self.u8(TAG_SIGNED)?;
+                self.u64(x as u64)
+            }
+            Some(Object::Bool(x)) => {
+                self.u8(TAG_BOOL)?;
+                self.u8(x as u8)
+            }
+            Some(Object::Str(x)) => {
+                self.u8(TAG_STR)?;
+                self.u16(x.literal)?;
+                self.u16(x.start)?;
+                self.u16(x.len)
+            }
+            Some(Object::Nil) => self.u8(TAG_NIL),
+            // Heap contents aren't part of the format.
+            Some(Object::Ref(_)) => Err(SnapshotError::Unsupported),
+        }
+    }
+}
+
+pub(crate) struct Reader<'b> {
+    pub(crate) buf: &'b [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'b> Reader<'b> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'b [u8], SnapshotError> {
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(SnapshotError::Malformed);
+        }
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let mut raw = [0; 2];
+        raw.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(raw))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut raw = [0; 8];
+        raw.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    pub(crate) fn object(&mut self) -> Result<Option<Object>, SnapshotError> {
+        match self.u8()? {
+            TAG_NONE => Ok(None),
+            TAG_UNSIGNED => Ok(Some(Object::Unsigned(self.u64()?))),
+            TAG_SIGNED => Ok(Some(Object::Signed(self.u64()? as i64))),
+            TAG_BOOL => match self.u8()? {
+                0 => Ok(Some(Object::Bool(false))),
+                1 => Ok(Some(Object::Bool(true))),
+                _ => Err(SnapshotError::Malformed),
+            },
+            TAG_NIL => Ok(Some(Object::Nil)),
+            TAG_STR => Ok(Some(Object::Str(StrRef {
+                literal: self.u16()?,
+                start: self.u16()?,
+                len: self.u16()?,
+            }))),
+            _ => Err(SnapshotError::Malformed),
+        }
+    }
+}
+
+impl<'a> Process<'a> {
+    /// Fingerprint of the code, literals and strings this process runs.
+    /// Snapshots can only be restored into a process with the same
+    /// fingerprint.
+    pub fn code_fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a(0xcbf2_9ce4_8422_2325);
+        self.code.hash(&mut hasher);
+        self.literals.hash(&mut hasher);
+        self.strings.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Writes the process state into `buf`, returning the number of bytes
+    /// used.
+    ///
+    /// Heap contents aren't saved, so this fails with `Unsupported` if the
+    /// stack, locals or registers hold a reference to a heap object.
+    /// Memory and globals aren't saved either.
+    pub fn snapshot(&self, buf: &mut [u8]) -> Result<usize, SnapshotError> {
+        let mut w = Writer { buf, pos: 0 };
+
+        w.bytes(MAGIC)?;
+        w.u8(VERSION)?;
+        w.u64(self.code_fingerprint())?;
+        w.u64(self.ip as u64)?;
+
+        w.u8(self.stack.len() as u8)?;
+        for obj in &self.stack {
+            w.object(Some(*obj))?;
+        }
+
+        w.u8(self.callstack.len() as u8)?;
+        for frame in &self.callstack {
+            w.u64(frame.ip as u64)?;
+            w.u8(frame.base as u8)?;
+            w.u8(frame.args as u8)?;
+            w.u8(frame.locals as u8)?;
+            match frame.results {
+                None => w.u8(0)?,
+                Some(results) => {
+                    w.u8(1)?;
+                    w.u8(results as u8)?;
+                }
+            }
+        }
+
+        w.u8(self.locals.len() as u8)?;
+        for obj in &self.locals {
+            w.object(*obj)?;
+        }
+
+        w.u8(self.scratch.len() as u8)?;
+        for obj in &self.scratch {
+            w.object(*obj)?;
+        }
+
+        Ok(w.pos)
+    }
+
+    /// Replaces the process state with one previously written by `snapshot`.
+    /// On error the process is left unchanged.
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = Reader { buf: data, pos: 0 };
+
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::Malformed);
+        }
+        if r.u8()? != VERSION {
+            return Err(SnapshotError::UnsupportedVersion);
+        }
+        if r.u64()? != self.code_fingerprint() {
+            return Err(SnapshotError::CodeMismatch);
+        }
+
+        let ip = r.u64()? as usize;
+
+        let mut stack = Vec::new();
+        for _ in 0..r.u8()? {
+            let obj = match r.object()? {
+                Some(obj) => obj,
+                None => return Err(SnapshotError::Malformed),
+            };
+            if stack.push(obj).is_err() {
+                return Err(SnapshotError::Malformed);
+            }
+        }
+
+        let mut callstack = Vec::new();
+        for _ in 0..r.u8()? {
+            let frame = StackFrame {
+                ip: r.u64()? as usize,
+                base: r.u8()? as usize,
+                args: r.u8()? as usize,
+                locals: r.u8()? as usize,
+                results: match r.u8()? {
+                    0 => None,
+                    1 => Some(r.u8()? as usize),
+                    _ => return Err(SnapshotError::Malformed),
+                },
+            };
+            if callstack.push(frame).is_err() {
+                return Err(SnapshotError::Malformed);
+            }
+        }
+
+        let mut locals = Vec::new();
+        for _ in 0..r.u8()? {
+            if locals.push(r.object()?).is_err() {
+                return Err(SnapshotError::Malformed);
+            }
+        }
+
+        let mut scratch = Vec::new();
+        if r.u8()? as usize != self.scratch.len() {
+            return Err(SnapshotError::Malformed);
+        }
+        for _ in 0..self.scratch.len() {
+            if scratch.push(r.object()?).is_err() {
+                return Err(SnapshotError::Malformed);
+            }
+        }
+
+        if r.pos != data.len() {
+            return Err(SnapshotError::Malformed);
+        }
+
+        self.ip = ip;
+        self.stack = stack;
+        self.callstack = callstack;
+        self.locals = locals;
+        self.scratch = scratch;
+        Ok(())
+    }
+}
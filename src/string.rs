@@ -0,0 +1,266 @@
+use core::cmp::{self, Ordering};
+
+use heap::{self, HeapRef};
+use {HaltReason, Object, Process};
+
+/// A handle to a byte-string literal, or a slice of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StrRef {
+    pub(crate) literal: u16,
+    pub(crate) start: u16,
+    pub(crate) len: u16,
+}
+
+// Strings are either literals or byte arrays on the heap.
+#[derive(Clone, Copy)]
+enum Text<'a> {
+    Literal(StrRef, &'a [u8]),
+    Heap(HeapRef, usize),
+}
+
+impl<'a> Text<'a> {
+    fn len(&self) -> usize {
+        match *self {
+            Text::Literal(_, bytes) => bytes.len(),
+            Text::Heap(_, len) => len,
+        }
+    }
+}
+
+impl<'a> Process<'a> {
+    fn text(&self, obj: Object) -> Result<Text<'a>, HaltReason> {
+        match obj {
+            Object::Str(s) => {
+                let bytes = match self.strings.get(s.literal as usize) {
+                    Some(bytes) => *bytes,
+                    None => return Err(HaltReason::InvalidReference),
+                };
+
+                let start = s.start as usize;
+                let end = start + s.len as usize;
+                if end > bytes.len() {
+                    return Err(HaltReason::InvalidReference);
+                }
+                Ok(Text::Literal(s, &bytes[start..end]))
+            }
+            Object::Ref(r) => Ok(Text::Heap(r, self.heap.bytes_len(r)?)),
+            _ => Err(HaltReason::TypeError),
+        }
+    }
+
+    fn text_byte(&self, text: Text, idx: usize) -> u8 {
+        match text {
+            Text::Literal(_, bytes) => bytes[idx],
+            Text::Heap(r, _) => self.heap.byte(r, idx),
+        }
+    }
+
+    /// Copies a string, either a literal or a byte array, into `buf` and
+    /// returns the part of `buf` it fills.
+    pub fn read_string<'b>(&self, obj: Object, buf: &'b mut [u8]) -> Result<&'b [u8], HaltReason> {
+        let text = self.text(obj)?;
+        if text.len() > buf.len() {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+
+        for (i, byte) in buf[..text.len()].iter_mut().enumerate() {
+            *byte = self.text_byte(text, i);
+        }
+        Ok(&buf[..text.len()])
+    }
+
+    pub(crate) fn literal_str(&mut self, idx: u16) -> Result<(), HaltReason> {
+        let len = match self.strings.get(idx as usize) {
+            Some(bytes) if bytes.len() <= u16::max_value() as usize => bytes.len(),
+            _ => return Err(HaltReason::InvalidLiteral),
+        };
+
+        self.push_new(Object::Str(StrRef {
+            literal: idx,
+            start: 0,
+            len: len as u16,
+        }))
+    }
+
+    pub(crate) fn str_length(&mut self) -> Result<(), HaltReason> {
+        let obj = self.pop()?;
+        let len = self.text(obj)?.len();
+        self.push_new(Object::Unsigned(len as u64))
+    }
+
+    pub(crate) fn str_byte(&mut self) -> Result<(), HaltReason> {
+        let idx = self.pop_as::<u64>()?;
+        let obj = self.pop()?;
+        let text = self.text(obj)?;
+
+        if idx >= text.len() as u64 {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+        let byte = self.text_byte(text, idx as usize);
+        self.push_new(Object::Unsigned(byte.into()))
+    }
+
+    pub(crate) fn str_slice(&mut self) -> Result<(), HaltReason> {
+        let end = self.pop_as::<u64>()?;
+        let start = self.pop_as::<u64>()?;
+        let obj = match self.stack.last() {
+            Some(obj) => *obj,
+            None => return Err(HaltReason::StackUnderflow),
+        };
+
+        if start > end || end > self.text(obj)?.len() as u64 {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+        let start = start as usize;
+        let len = (end as usize) - start;
+
+        // Slices of byte arrays are copies. Room is made while the original
+        // is still on the stack, so a collection keeps it alive.
+        if let Object::Ref(_) = obj {
+            self.reserve(heap::words_for(heap::Kind::Bytes, len))?;
+        }
+
+        let obj = self.pop()?;
+        let slice = match self.text(obj)? {
+            Text::Literal(s, _) => Object::Str(StrRef {
+                literal: s.literal,
+                start: s.start + start as u16,
+                len: len as u16,
+            }),
+            Text::Heap(r, _) => self.heap.alloc_slice(r, start, len),
+        };
+        self.push_new(slice)
+    }
+
+    pub(crate) fn str_compare(&mut self) -> Result<(), HaltReason> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        let ordering = self.compare_strings(a, b)?;
+        self.push_new(Object::Signed(ordering as i64))
+    }
+
+    /// Orders two strings, either literals or byte arrays, byte by byte.
+    pub(crate) fn compare_strings(&self, a: Object, b: Object) -> Result<Ordering, HaltReason> {
+        let (a, b) = (self.text(a)?, self.text(b)?);
+        for i in 0..cmp::min(a.len(), b.len()) {
+            match self.text_byte(a, i).cmp(&self.text_byte(b, i)) {
+                Ordering::Equal => {},
+                x => return Ok(x),
+            }
+        }
+        Ok(a.len().cmp(&b.len()))
+    }
+
+    // Checks that `len` bytes fit at `offset` in the byte array on top of the
+    // stack.
+    fn buffer_at(&self, offset: u64, len: usize) -> Result<(HeapRef, usize), HaltReason> {
+        let buf = match self.stack.last() {
+            Some(Object::Ref(r)) => *r,
+            Some(_) => return Err(HaltReason::TypeError),
+            None => return Err(HaltReason::StackUnderflow),
+        };
+
+        let room = self.heap.bytes_len(buf)? as u64;
+        if offset > room || room - offset < len as u64 {
+            return Err(HaltReason::IndexOutOfBounds);
+        }
+        Ok((buf, offset as usize))
+    }
+
+    pub(crate) fn str_concat(&mut self) -> Result<(), HaltReason> {
+        let obj = self.pop()?;
+        let offset = self.pop_as::<u64>()?;
+        let text = self.text(obj)?;
+
+        let (buf, at) = self.buffer_at(offset, text.len())?;
+        for i in 0..text.len() {
+            let byte = self.text_byte(text, i);
+            self.heap.set_byte(buf, at + i, byte);
+        }
+        self.push_new(Object::Unsigned((at + text.len()) as u64))
+    }
+
+    pub(crate) fn format_unsigned(&mut self) -> Result<(), HaltReason> {
+        let x = self.pop_as::<u64>()?;
+        self.write_decimal(false, x)
+    }
+
+    pub(crate) fn format_signed(&mut self) -> Result<(), HaltReason> {
+        let x = self.pop_as::<i64>()?;
+        self.write_decimal(x < 0, x.wrapping_abs() as u64)
+    }
+
+    fn write_decimal(&mut self, negative: bool, mut x: u64) -> Result<(), HaltReason> {
+        let offset = self.pop_as::<u64>()?;
+
+        // Digits come out least significant first.
+        let mut digits = [0u8; 20];
+        let mut n = 0;
+        loop {
+            digits[n] = b'0' + (x % 10) as u8;
+            n += 1;
+            x /= 10;
+            if x == 0 {
+                break;
+            }
+        }
+
+        let (buf, mut at) = self.buffer_at(offset, n + negative as usize)?;
+        if negative {
+            self.heap.set_byte(buf, at, b'-');
+            at += 1;
+        }
+        for digit in digits[..n].iter().rev() {
+            self.heap.set_byte(buf, at, *digit);
+            at += 1;
+        }
+        self.push_new(Object::Unsigned(at as u64))
+    }
+
+    fn parse_digits(&self, text: Text, start: usize) -> Option<u64> {
+        if start >= text.len() {
+            return None;
+        }
+
+        let mut x: u64 = 0;
+        for i in start..text.len() {
+            let digit = match self.text_byte(text, i) {
+                b @ b'0'..=b'9' => b - b'0',
+                _ => return None,
+            };
+            x = x.checked_mul(10)?.checked_add(digit.into())?;
+        }
+        Some(x)
+    }
+
+    fn push_parsed(&mut self, parsed: Option<Object>) -> Result<(), HaltReason> {
+        match parsed {
+            Some(x) => {
+                self.push_new(x)?;
+                self.push_new(Object::Bool(true))
+            }
+            None => self.push_new(Object::Bool(false)),
+        }
+    }
+
+    pub(crate) fn parse_unsigned(&mut self) -> Result<(), HaltReason> {
+        let obj = self.pop()?;
+        let text = self.text(obj)?;
+
+        let parsed = self.parse_digits(text, 0).map(Object::Unsigned);
+        self.push_parsed(parsed)
+    }
+
+    pub(crate) fn parse_signed(&mut self) -> Result<(), HaltReason> {
+        let obj = self.pop()?;
+        let text = self.text(obj)?;
+
+        let negative = text.len() > 0 && self.text_byte(text, 0) == b'-';
+        let parsed = match self.parse_digits(text, negative as usize) {
+            Some(x) if negative && x <= 1 << 63 => Some(Object::Signed(0i64.wrapping_sub(x as i64))),
+            Some(x) if !negative && x < 1 << 63 => Some(Object::Signed(x as i64)),
+            _ => None,
+        };
+        self.push_parsed(parsed)
+    }
+}
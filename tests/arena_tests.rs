@@ -0,0 +1,263 @@
+extern crate rlang;
+use rlang::*;
+
+#[test]
+fn arena_heap() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::MakeArray(2),
+        Instruction::LiteralUnsigned(1),
+        Instruction::Index,
+    ];
+
+    let mut arena = [Object::Unsigned(0); 8];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(2)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn arena_out_of_memory() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::MakeArray(2),
+        Instruction::LiteralUnsigned(3),
+        Instruction::MakeArray(1),
+    ];
+
+    let mut arena = [Object::Unsigned(0); 6];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    assert_eq!(x.run(64), Err(HaltReason::OutOfMemory))
+}
+
+#[test]
+fn arena_compaction() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(42),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::LiteralUnsigned(50),
+        Instruction::StoreReg(1),
+        // Each iteration leaves a garbage array behind.
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(2),
+        Instruction::LoadReg(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::StoreRegKeep(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::NeqUnsigned,
+        Instruction::JumpIfTrue(5),
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+    ];
+
+    let mut arena = [Object::Unsigned(0); 9];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    let ret = x.run(10_000);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(42)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn collect_deep_chain() {
+    use std::time::{Duration, Instant};
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::StoreReg(0),
+        Instruction::LiteralUnsigned(20_000),
+        Instruction::StoreReg(1),
+        // Wrap register 0 in another array until the counter reaches zero.
+        Instruction::LoadReg(0),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::LoadReg(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::StoreRegKeep(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::NeqUnsigned,
+        Instruction::JumpIfTrue(4),
+        Instruction::Ret,
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::Length,
+        Instruction::Ret,
+    ];
+
+    let mut arena = vec![Object::Unsigned(0); 60_000];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    assert_eq!(x.call(0, &[], 1_000_000), Ok(&*vec![]));
+
+    // Marking used to sweep the arena once per level of nesting.
+    let start = Instant::now();
+    x.collect_garbage();
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    assert_eq!(x.call(15, &[], 64), Ok(&*vec![Object::Unsigned(1)]));
+}
+
+#[test]
+fn clone_and_fork_arena() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(5),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::Ret,
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::Ret,
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(6),
+        Instruction::SetIndex,
+        Instruction::Ret,
+    ];
+
+    let mut arena = [Object::Unsigned(0); 8];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+    assert_eq!(x.call(0, &[], 64), Ok(&*vec![]));
+
+    let mut y = x.clone();
+
+    assert_eq!(y.call(4, &[], 64), Err(HaltReason::InvalidReference));
+    assert_eq!(y.call(0, &[], 64), Err(HaltReason::OutOfMemory));
+
+    let mut other = [Object::Unsigned(0); 8];
+    let mut z = x.fork_into(&mut other).unwrap();
+
+    assert_eq!(z.call(8, &[], 64), Ok(&*vec![]));
+    assert_eq!(z.call(4, &[], 64), Ok(&*vec![Object::Unsigned(6)]));
+    assert_eq!(x.call(4, &[], 64), Ok(&*vec![Object::Unsigned(5)]));
+    assert_eq!(x.call(0, &[], 64), Ok(&*vec![]));
+
+    assert_eq!(x.fork_into(&mut [Object::Unsigned(0); 2]).err(), Some(HaltReason::OutOfMemory));
+}
+
+#[test]
+fn intrinsic_heap_args() {
+    fn check_refs(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let array = proc.pop_args::<HeapRef>()?;
+        let missing = proc.pop_args::<Option<HeapRef>>()?;
+        proc.push_results((missing.is_none(), array))
+    }
+
+    let ins = vec![
+        Instruction::LiteralNil,
+        Instruction::MakeArray(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::Length,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        check_refs
+    ];
+
+    let mut arena = [Object::Unsigned(0); 4];
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).heap(&mut arena).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Bool(true), Object::Unsigned(0)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn compare_arrays_and_tuples() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::Eq,
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::MakeTuple(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::MakeTuple(1),
+        Instruction::Neq,
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeTuple(1),
+        Instruction::Eq,
+        Instruction::LiteralUnsigned(1),
+        Instruction::MakeArray(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(2),
+        Instruction::Compare,
+        Instruction::LiteralUnsigned(2),
+        Instruction::MakeArray(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(2),
+        Instruction::Compare,
+    ];
+
+    let mut arena = [Object::Unsigned(0); 16];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(false),
+        Object::Signed(-1),
+        Object::Signed(1),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn compare_cycles() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(1),
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LoadReg(0),
+        Instruction::SetIndex,
+        Instruction::LoadReg(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LoadReg(1),
+        Instruction::SetIndex,
+        Instruction::LoadReg(0),
+        Instruction::LoadReg(0),
+        Instruction::Eq,
+        Instruction::LoadReg(0),
+        Instruction::LoadReg(1),
+        Instruction::Eq,
+    ];
+
+    let mut arena = [Object::Unsigned(0); 8];
+    let mut x = ProcessBuilder::new(&ins).heap(&mut arena).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Bool(true)]);
+    assert_eq!(ret, Err(HaltReason::StackOverflow))
+}
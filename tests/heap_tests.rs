@@ -0,0 +1,239 @@
+#![cfg(feature = "alloc")]
+
+extern crate rlang;
+use rlang::*;
+
+#[test]
+fn array_index_and_length() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(10),
+        Instruction::LiteralUnsigned(20),
+        Instruction::LiteralUnsigned(30),
+        Instruction::MakeArray(3),
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(-5),
+        Instruction::SetIndex,
+        Instruction::PopSlot1,
+        Instruction::Length,
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(1),
+        Instruction::Index,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(3), Object::Signed(-5)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn bytes_and_tuples() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(4),
+        Instruction::MakeBytes,
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(0xab),
+        Instruction::SetIndex,
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(3),
+        Instruction::Index,
+        Instruction::LiteralBool(true),
+        Instruction::MakeTuple(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralBool(false),
+        Instruction::SetIndex,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(0xab)]);
+    assert_eq!(ret, Err(HaltReason::TypeError))
+}
+
+#[test]
+fn index_out_of_bounds() {
+    let reason = Process::new(&vec![
+        Instruction::MakeArray(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::IndexOutOfBounds))
+}
+
+#[test]
+fn heap_limit() {
+    let literals = vec![
+        Object::Unsigned(u64::from(u32::max_value())),
+    ];
+
+    let reason = ProcessBuilder::new(&vec![
+        Instruction::LiteralIndexed(0),
+        Instruction::MakeBytes,
+    ]).literals(&literals[..]).build().run(64);
+
+    assert_eq!(reason, Err(HaltReason::OutOfMemory));
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(80),
+        Instruction::MakeBytes,
+        Instruction::LiteralUnsigned(80),
+        Instruction::MakeBytes,
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).heap_limit(20).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack().len(), 1);
+    assert_eq!(ret, Err(HaltReason::OutOfMemory))
+}
+
+#[test]
+fn collection_keeps_rooted_objects() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(42),
+        Instruction::MakeArray(1),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::LiteralUnsigned(200),
+        Instruction::StoreReg(1),
+        // Allocate garbage until the counter in register 1 reaches zero.
+        Instruction::LiteralUnsigned(0),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(2),
+        Instruction::LoadReg(1),
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::StoreRegKeep(1),
+        Instruction::LiteralUnsigned(0),
+        Instruction::NeqUnsigned,
+        Instruction::JumpIfTrue(6),
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(10_000);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(42)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn collection_frees_unreachable_objects() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(7),
+        Instruction::MakeArray(1),
+        Instruction::Ret,
+        Instruction::Index,
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let array = x.call(0, &[], 64).unwrap()[0];
+
+    x.reset();
+    assert_eq!(x.call(3, &[array, Object::Unsigned(0)], 64), Ok(&*vec![Object::Unsigned(7)]));
+
+    x.reset();
+    x.collect_garbage();
+    assert_eq!(x.call(3, &[array, Object::Unsigned(0)], 64), Err(HaltReason::InvalidReference));
+}
+
+#[test]
+fn stale_refs_are_detected() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(7),
+        Instruction::MakeArray(1),
+        Instruction::Ret,
+        Instruction::Index,
+        Instruction::Ret,
+        Instruction::LiteralUnsigned(8),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let array = x.call(0, &[], 64).unwrap()[0];
+
+    // The live array moves down to where the first one was.
+    x.reset();
+    assert_eq!(x.call(5, &[], 64), Ok(&*vec![]));
+    x.collect_garbage();
+    assert_eq!(x.call(3, &[array, Object::Unsigned(0)], 64), Err(HaltReason::InvalidReference));
+}
+
+#[test]
+fn clone_collection_leaves_globals_alone() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(5),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::LiteralUnsigned(111),
+        Instruction::MakeArray(1),
+        Instruction::StoreGlobal(0),
+        Instruction::Ret,
+        Instruction::LoadGlobal(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::Ret,
+    ];
+
+    let mut globals = [None];
+    let mut x = ProcessBuilder::new(&ins).globals(&mut globals).build();
+    assert_eq!(x.call(0, &[], 64), Ok(&*vec![]));
+    x.reset();
+
+    let mut y = x.clone();
+    y.collect_garbage();
+
+    assert_eq!(x.call(7, &[], 64), Ok(&*vec![Object::Unsigned(111)]));
+    assert_eq!(y.call(7, &[], 64), Err(HaltReason::InvalidReference));
+
+    x.collect_garbage();
+    assert_eq!(x.call(7, &[], 64), Ok(&*vec![Object::Unsigned(111)]));
+}
+
+#[test]
+fn cloned_heap_is_independent() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(5),
+        Instruction::MakeArray(1),
+        Instruction::StoreReg(0),
+        Instruction::Ret,
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Index,
+        Instruction::Ret,
+        Instruction::LoadReg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(6),
+        Instruction::SetIndex,
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.call(0, &[], 64), Ok(&*vec![]));
+
+    let mut y = x.clone();
+
+    assert_eq!(y.call(8, &[], 64), Ok(&*vec![]));
+    assert_eq!(y.call(4, &[], 64), Ok(&*vec![Object::Unsigned(6)]));
+    assert_eq!(x.call(4, &[], 64), Ok(&*vec![Object::Unsigned(5)]));
+}
@@ -162,30 +162,1366 @@ fn simple_signed_modulus() {
     assert_eq!(ret, Err(HaltReason::OutOfBounds))
 }
 
+#[test]
+fn unsigned_min_max_clamp() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(9),
+        Instruction::MinUnsigned,
+        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(9),
+        Instruction::MaxUnsigned,
+        Instruction::LiteralUnsigned(12),
+        Instruction::LiteralUnsigned(2),
+        Instruction::LiteralUnsigned(10),
+        Instruction::ClampUnsigned,
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::LiteralUnsigned(10),
+        Instruction::ClampUnsigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(3),
+        Object::Unsigned(9),
+        Object::Unsigned(10),
+        Object::Unsigned(2),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn signed_min_max_clamp() {
+    let ins = vec![
+        Instruction::LiteralSigned(-3),
+        Instruction::LiteralSigned(9),
+        Instruction::MinSigned,
+        Instruction::LiteralSigned(-3),
+        Instruction::LiteralSigned(-9),
+        Instruction::MaxSigned,
+        Instruction::LiteralSigned(-12),
+        Instruction::LiteralSigned(-10),
+        Instruction::LiteralSigned(10),
+        Instruction::ClampSigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(-3),
+        Object::Signed(-3),
+        Object::Signed(-10),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn abs_and_negate() {
+    let ins = vec![
+        Instruction::LiteralSigned(-5),
+        Instruction::AbsSigned,
+        Instruction::LiteralSigned(5),
+        Instruction::NegateSigned,
+        Instruction::LiteralIndexed(0),
+        Instruction::NegateSigned,
+    ];
+
+    let literals = vec![
+        Object::Signed(i64::min_value()),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Signed(5), Object::Signed(-5)]);
+    assert_eq!(ret, Err(HaltReason::Overflow));
+
+    let reason = ProcessBuilder::new(&[Instruction::LiteralIndexed(0), Instruction::AbsSigned])
+        .literals(&literals[..])
+        .build()
+        .run(64);
+
+    assert_eq!(reason, Err(HaltReason::Overflow))
+}
+
+#[test]
+fn unsigned_abs_and_negate() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(7),
+        Instruction::AbsUnsigned,
+        Instruction::LiteralUnsigned(0),
+        Instruction::NegateUnsigned,
+        Instruction::LiteralUnsigned(1),
+        Instruction::NegateUnsigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(7), Object::Unsigned(0)]);
+    assert_eq!(ret, Err(HaltReason::Overflow))
+}
+
+#[test]
+fn widening_unsigned_multiplication() {
+    let ins = vec![
+        Instruction::LiteralIndexed(0),
+        Instruction::LiteralIndexed(0),
+        Instruction::WideMultiplyUnsigned,
+        Instruction::LiteralIndexed(0),
+        Instruction::LiteralUnsigned(4),
+        Instruction::MulHiUnsigned,
+    ];
+
+    let literals = vec![
+        Object::Unsigned(u64::max_value()),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(u64::max_value() - 1),
+        Object::Unsigned(1),
+        Object::Unsigned(3),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn widening_signed_multiplication() {
+    let ins = vec![
+        Instruction::LiteralIndexed(0),
+        Instruction::LiteralSigned(-2),
+        Instruction::WideMultiplySigned,
+        Instruction::LiteralIndexed(0),
+        Instruction::LiteralSigned(-2),
+        Instruction::MulHiSigned,
+    ];
+
+    let literals = vec![
+        Object::Signed(i64::max_value()),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(-1),
+        Object::Unsigned(2),
+        Object::Signed(-1),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn wide_division() {
+    // (2^64 + 5) / 3 and -(2^64 + 5) / 3
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(5),
+        Instruction::LiteralUnsigned(3),
+        Instruction::WideDivideUnsigned,
+        Instruction::LiteralSigned(-2),
+        Instruction::LiteralIndexed(0),
+        Instruction::LiteralSigned(3),
+        Instruction::WideDivideSigned,
+    ];
+
+    let literals = vec![
+        Object::Unsigned(u64::max_value() - 4),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(6148914691236517207),
+        Object::Unsigned(0),
+        Object::Signed(-6148914691236517207),
+        Object::Signed(0),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn wide_division_errors() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(3),
+        Instruction::WideDivideUnsigned,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::Overflow));
+
+    let reason = Process::new(&vec![
+        Instruction::LiteralSigned(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(0),
+        Instruction::WideDivideSigned,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::DivisionByZero))
+}
+
+#[test]
+fn bit_counting() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(0xf0f0),
+        Instruction::PopCount,
+        Instruction::LiteralUnsigned(0xf0f0),
+        Instruction::LeadingZeros,
+        Instruction::LiteralUnsigned(0xf0f0),
+        Instruction::TrailingZeros,
+        Instruction::LiteralUnsigned(0),
+        Instruction::TrailingZeros,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(8),
+        Object::Unsigned(48),
+        Object::Unsigned(4),
+        Object::Unsigned(64),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn byte_swap_and_bit_reverse() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(0x1234),
+        Instruction::ByteSwap,
+        Instruction::LiteralUnsigned(1),
+        Instruction::BitReverse,
+        Instruction::LiteralSigned(1),
+        Instruction::BitReverse,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(0x3412_0000_0000_0000),
+        Object::Unsigned(1 << 63),
+    ]);
+    assert_eq!(ret, Err(HaltReason::TypeError))
+}
+
+#[test]
+fn bit_fields() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(0xabcd),
+        Instruction::LiteralUnsigned(4),
+        Instruction::LiteralUnsigned(8),
+        Instruction::BitExtract,
+        Instruction::LiteralUnsigned(0xabcd),
+        Instruction::LiteralUnsigned(0x1ff),
+        Instruction::LiteralUnsigned(4),
+        Instruction::LiteralUnsigned(8),
+        Instruction::BitInsert,
+        Instruction::LiteralUnsigned(0xabcd),
+        Instruction::LiteralUnsigned(64),
+        Instruction::LiteralUnsigned(8),
+        Instruction::BitExtract,
+        Instruction::LiteralUnsigned(0xabcd),
+        Instruction::LiteralUnsigned(8),
+        Instruction::LiteralUnsigned(100),
+        Instruction::BitExtract,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(0xbc),
+        Object::Unsigned(0xaffd),
+        Object::Unsigned(0),
+        Object::Unsigned(0xab),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn signed_bit_ops() {
+    let ins = vec![
+        Instruction::LiteralSigned(-8),
+        Instruction::LiteralSigned(0x0f),
+        Instruction::BitAndSigned,
+        Instruction::LiteralSigned(-8),
+        Instruction::LiteralSigned(3),
+        Instruction::BitOrSigned,
+        Instruction::LiteralSigned(-8),
+        Instruction::BitNotSigned,
+        Instruction::LiteralSigned(-8),
+        Instruction::LiteralSigned(-1),
+        Instruction::BitXorSigned,
+        Instruction::LiteralSigned(-3),
+        Instruction::LiteralUnsigned(2),
+        Instruction::BitLShiftSigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(8),
+        Object::Signed(-5),
+        Object::Signed(7),
+        Object::Signed(7),
+        Object::Signed(-12),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn arithmetic_shift_right() {
+    let ins = vec![
+        Instruction::LiteralSigned(-100),
+        Instruction::LiteralUnsigned(2),
+        Instruction::BitRShiftSigned,
+        Instruction::LiteralSigned(-100),
+        Instruction::LiteralUnsigned(200),
+        Instruction::BitRShiftSigned,
+        Instruction::LiteralSigned(100),
+        Instruction::LiteralUnsigned(200),
+        Instruction::BitRShiftSigned,
+        Instruction::LiteralSigned(100),
+        Instruction::LiteralSigned(1),
+        Instruction::BitRShiftSigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(-25),
+        Object::Signed(-1),
+        Object::Signed(0),
+        Object::Signed(100),
+    ]);
+    assert_eq!(ret, Err(HaltReason::TypeError))
+}
+
+#[test]
+fn sign_extension() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(0xff),
+        Instruction::SignExtend8,
+        Instruction::LiteralUnsigned(0x7fff),
+        Instruction::SignExtend16,
+        Instruction::LiteralUnsigned(0x8000),
+        Instruction::SignExtend16,
+        Instruction::LiteralIndexed(0),
+        Instruction::SignExtend32,
+    ];
+
+    let literals = vec![
+        Object::Unsigned(0x1_ffff_fffe),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(-1),
+        Object::Signed(0x7fff),
+        Object::Signed(-0x8000),
+        Object::Signed(-2),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn generic_equality() {
+    let ins = vec![
+        Instruction::LiteralBool(true),
+        Instruction::LiteralBool(true),
+        Instruction::Eq,
+        Instruction::LiteralBool(true),
+        Instruction::LiteralBool(false),
+        Instruction::Neq,
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(1),
+        Instruction::Eq,
+        Instruction::LiteralNil,
+        Instruction::LiteralNil,
+        Instruction::Eq,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(true),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn generic_compare() {
+    let ins = vec![
+        Instruction::LiteralSigned(-2),
+        Instruction::LiteralSigned(3),
+        Instruction::Compare,
+        Instruction::LiteralBool(true),
+        Instruction::LiteralBool(false),
+        Instruction::Compare,
+        Instruction::LiteralUnsigned(7),
+        Instruction::LiteralUnsigned(7),
+        Instruction::Compare,
+        Instruction::LiteralNil,
+        Instruction::LiteralUnsigned(7),
+        Instruction::Compare,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(-1),
+        Object::Signed(1),
+        Object::Signed(0),
+        Object::Signed(1),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
 #[test]
 fn branch() {
     let ins = vec![
-        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(3),
+        Instruction::Branch,
+        Instruction::LiteralUnsigned(100),
+        Instruction::LiteralUnsigned(50),
+        Instruction::LiteralUnsigned(25),
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn conditional_branch_true() {
+    let ins = vec![
+        Instruction::LiteralBool(true),
+        Instruction::LiteralUnsigned(4),
+        Instruction::BranchTrue,
+        Instruction::LiteralUnsigned(100),
+        Instruction::LiteralUnsigned(50),
+        Instruction::LiteralUnsigned(25),
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn conditional_branch_false() {
+    let ins = vec![
+        Instruction::LiteralBool(false),
+        Instruction::LiteralUnsigned(4),
+        Instruction::BranchTrue,
+        Instruction::LiteralUnsigned(100),
+        Instruction::LiteralUnsigned(50),
+        Instruction::LiteralUnsigned(25),
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(100), Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn branch_false() {
+    let ins = vec![
+        Instruction::LiteralBool(false),
+        Instruction::LiteralUnsigned(4),
+        Instruction::BranchFalse,
+        Instruction::LiteralUnsigned(100),
+        Instruction::LiteralBool(true),
+        Instruction::LiteralUnsigned(8),
+        Instruction::BranchFalse,
+        Instruction::LiteralUnsigned(50),
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(50)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn select() {
+    let ins = vec![
+        Instruction::LiteralBool(true),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::Select,
+        Instruction::LiteralBool(false),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(-2),
+        Instruction::Select,
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::Select,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(1), Object::Signed(-2)]);
+    assert_eq!(ret, Err(HaltReason::TypeError))
+}
+
+#[test]
+fn calling_incrementer() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(5),
+        Instruction::Branch,
+        Instruction::LiteralUnsigned(1),
+        Instruction::AddUnsigned,
+        Instruction::Ret,
+        Instruction::LiteralUnsigned(10),
+        Instruction::LiteralUnsigned(2),
+        Instruction::Call,
+        Instruction::LiteralUnsigned(2),
+        Instruction::Call,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(12)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+#[should_panic(expected = "Called expected_panic from intrinsic")]
+fn simple_intrinsic() {
+
+    fn expected_panic(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        panic!("Called expected_panic from intrinsic")
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        expected_panic
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert!(false);
+}
+
+#[test]
+fn intrinsic_mutation() {
+    fn triple_top(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let top = proc.pub_pop_as::<u64>().unwrap();
+        let new_top = top * 3;
+        assert!(proc.pub_push(Object::Unsigned(new_top)));
+        Ok(())
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(13),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        triple_top
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(39)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn push_indexed_literal() {
+    let ins = vec![
+        Instruction::LiteralIndexed(0),
+    ];
+    
+    let literals = vec![
+        Object::Unsigned(50),
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(50)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn stack_underflow() {
+    let ins = vec![
+        Instruction::AddUnsigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let reason = x.run(64);
+
+    assert_eq!(reason, Err(HaltReason::StackUnderflow))
+}
+
+#[test]
+fn type_error() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(1),
+        Instruction::AddUnsigned
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let reason = x.run(64);
+
+    assert_eq!(reason, Err(HaltReason::TypeError))
+}
+
+#[test]
+fn empty_scratch() {
+    let reason = Process::new(&vec![
+        Instruction::PopSlot1,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::EmptyScratch))
+}
+
+#[test]
+fn cycle_limit() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Branch,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::CycleLimit))
+}
+
+#[test]
+fn invalid_intrinsic() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidIntrinsic))
+}
+
+#[test]
+fn invalid_literal() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralIndexed(0),
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidLiteral))
+}
+
+#[test]
+fn intrinsic_typed_args() {
+    fn sub_and_compare(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let (x, y) = proc.pop_args::<(u64, u64)>()?;
+        proc.push_results((x - y, x > y))
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(10),
+        Instruction::LiteralUnsigned(4),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        sub_and_compare
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(6), Object::Bool(true)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn intrinsic_errors_halt() {
+    fn add_unsigned(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let (x, y) = proc.pop_args::<(u64, u64)>()?;
+        proc.push_results(x + y)
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralSigned(2),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        add_unsigned
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(1), Object::Signed(2)]);
+    assert_eq!(ret, Err(HaltReason::TypeError));
+
+    let mut x = ProcessBuilder::new(&ins[2..]).intrinsics(&intrinsics[..]).build();
+
+    assert_eq!(x.run(64), Err(HaltReason::StackUnderflow));
+}
+
+#[test]
+fn intrinsic_args_errors_leave_stack_untouched() {
+    fn check_errors(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        assert_eq!(proc.pop_args::<(u64, u64)>(), Err(HaltReason::TypeError));
+        assert_eq!(proc.pop_args::<(bool, u64, u64)>(), Err(HaltReason::StackUnderflow));
+        assert_eq!(proc.peek_args::<(i64, u64)>(), Ok((-1, 2)));
+        Ok(())
+    }
+
+    let ins = vec![
+        Instruction::LiteralSigned(-1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        check_errors
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Signed(-1), Object::Unsigned(2)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn intrinsic_push_results_overflow() {
+    fn fill(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        for i in 0..31 {
+            proc.push_results(i as u64)?;
+        }
+        assert_eq!(proc.push_results((true, false)), Err(HaltReason::StackOverflow));
+        proc.push_results(true)
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        fill
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack().len(), 32);
+    assert_eq!(x.stack()[31], Object::Bool(true));
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn nil() {
+    let ins = vec![
+        Instruction::LiteralNil,
+        Instruction::IsNil,
+        Instruction::LiteralUnsigned(0),
+        Instruction::IsNil,
+        Instruction::LiteralNil,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Bool(true), Object::Bool(false), Object::Nil]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn intrinsic_optional_values() {
+    fn halve_if_even(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let x = proc.pop_args::<Option<u64>>()?;
+        proc.push_results(x.and_then(|x| if x % 2 == 0 { Some(x / 2) } else { None }))
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(10),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::LiteralUnsigned(3),
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        halve_if_even
+    ];
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(5), Object::Nil]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds));
+
+    assert_eq!(x.peek_args::<(Option<u64>, Option<u64>)>(), Ok((Some(5), None)));
+    assert_eq!(x.pop_args::<Option<bool>>(), Ok(None));
+    assert_eq!(x.pop_args::<Option<bool>>(), Err(HaltReason::TypeError));
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(7),
+        Instruction::PushSlot2,
+        Instruction::LiteralUnsigned(7),
+        Instruction::Call,
+        Instruction::PopSlot2,
+        Instruction::LiteralUnsigned(9),
+        Instruction::Branch,
+        Instruction::LiteralSigned(-3),
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.run(5), Err(HaltReason::CycleLimit));
+
+    let mut buf = [0u8; 256];
+    let len = x.snapshot(&mut buf).unwrap();
+
+    let mut y = Process::new(&ins);
+    y.restore(&buf[..len]).unwrap();
+
+    let ret = y.run(64);
+
+    assert_eq!(y.stack(), &*vec![Object::Signed(-3), Object::Unsigned(7)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn snapshot_rejects_other_code() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+    ];
+    let other = vec![
+        Instruction::LiteralUnsigned(2),
+    ];
+
+    let mut buf = [0u8; 256];
+    let len = Process::new(&ins).snapshot(&mut buf).unwrap();
+
+    let mut x = Process::new(&other);
+    assert_eq!(x.restore(&buf[..len]), Err(SnapshotError::CodeMismatch));
+    assert_eq!(Process::new(&ins).restore(&buf[..len - 1]), Err(SnapshotError::Malformed));
+    assert_eq!(Process::new(&ins).snapshot(&mut buf[..4]), Err(SnapshotError::BufferTooSmall));
+}
+
+#[test]
+fn cloned_process_runs_independently() {
+    let ins = vec![
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(2),
+        Instruction::MultiplyUnsigned,
+    ];
+
+    let mut x = Process::new(&ins);
+    assert!(x.pub_push(Object::Unsigned(5)));
+    assert_eq!(x.run(2), Err(HaltReason::CycleLimit));
+
+    let mut y = x.clone();
+    assert!(y.pub_push(Object::Unsigned(10)));
+    assert!(y.pub_push(Object::Unsigned(3)));
+    assert_eq!(y.run(1), Err(HaltReason::CycleLimit));
+
+    assert_eq!(x.run(64), Err(HaltReason::OutOfBounds));
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(10)]);
+
+    assert_eq!(y.run(64), Err(HaltReason::OutOfBounds));
+    assert_eq!(y.stack(), &*vec![Object::Unsigned(5), Object::Unsigned(10), Object::Unsigned(6)]);
+}
+
+#[test]
+fn record_and_replay_intrinsics() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static READINGS: AtomicUsize = AtomicUsize::new(0);
+
+    fn read_sensor(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let reading = READINGS.fetch_add(1, Ordering::SeqCst) as u64 * 10;
+        assert!(proc.pub_push(Object::Unsigned(reading)));
+        Ok(())
+    }
+
+    fn drop_top(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        proc.pub_pop_as::<u64>().unwrap();
+        Ok(())
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::LiteralUnsigned(1),
+        Instruction::Intrinsic,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::AddUnsigned,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        read_sensor,
+        drop_top,
+    ];
+
+    READINGS.store(1, Ordering::SeqCst);
+
+    let mut buf = [0u8; 256];
+    let mut recorder = LogRecorder::new(&mut buf);
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    let ret = x.run_recording(64, &mut recorder);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(40)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds));
+
+    let mut replayer = LogReplayer::new(recorder.log());
+
+    let mut y = Process::new(&ins);
+    let ret = y.run_replaying(64, &mut replayer);
+
+    assert_eq!(y.stack(), &*vec![Object::Unsigned(40)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds));
+}
+
+#[test]
+fn replay_divergence() {
+    fn push_one(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        assert!(proc.pub_push(Object::Unsigned(1)));
+        Ok(())
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        push_one,
+    ];
+
+    let mut buf = [0u8; 256];
+    let mut recorder = LogRecorder::new(&mut buf);
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    assert_eq!(x.run_recording(64, &mut recorder), Err(HaltReason::OutOfBounds));
+
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::Intrinsic,
+    ]).run_replaying(64, &mut LogReplayer::new(recorder.log()));
+
+    assert_eq!(reason, Err(HaltReason::ReplayDivergence));
+
+    let reason = Process::new(&ins).run_recording(64, &mut LogRecorder::new(&mut [0u8; 256]));
+
+    assert_eq!(reason, Err(HaltReason::InvalidIntrinsic));
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    let reason = x.run_recording(64, &mut LogRecorder::new(&mut [0u8; 4]));
+
+    assert_eq!(reason, Err(HaltReason::RecordingFull));
+}
+
+#[test]
+fn reset_and_reuse() {
+    let ins = vec![
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::PopSlot1,
+        Instruction::MultiplyUnsigned,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    for i in 0..4 {
+        x.reset_with_args(&[Object::Unsigned(i)]).unwrap();
+        let ret = x.run(64);
+
+        assert_eq!(x.stack(), &*vec![Object::Unsigned(i * i)]);
+        assert_eq!(ret, Err(HaltReason::OutOfBounds))
+    }
+
+    x.reset();
+    assert_eq!(x.run(64), Err(HaltReason::StackUnderflow));
+
+    assert_eq!(x.reset_with_args(&[Object::Bool(true); 33]), Err(HaltReason::StackOverflow));
+    assert_eq!(x.stack(), &*vec![]);
+}
+
+#[test]
+fn call_exported_functions() {
+    let ins = vec![
+        // square(x) -> x * x
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::PopSlot1,
+        Instruction::MultiplyUnsigned,
+        Instruction::Ret,
+        // divmod(x, y) -> (x / y, x % y), via a nested call to divide
+        Instruction::PushSlot2,
+        Instruction::PushSlot1,
+        Instruction::PopSlot1,
+        Instruction::PopSlot2,
+        Instruction::LiteralUnsigned(15),
+        Instruction::Call,
+        Instruction::PopSlot1,
+        Instruction::PopSlot2,
+        Instruction::ModulusUnsigned,
+        Instruction::Ret,
+        Instruction::DivideUnsigned,
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    assert_eq!(x.call(0, &[Object::Unsigned(7)], 64), Ok(&*vec![Object::Unsigned(49)]));
+
+    x.reset();
+    assert_eq!(x.call(5, &[Object::Unsigned(17), Object::Unsigned(5)], 64), Ok(&*vec![Object::Unsigned(3), Object::Unsigned(2)]));
+
+    x.reset();
+    assert_eq!(x.call(0, &[], 64), Err(HaltReason::StackUnderflow));
+
+    x.reset();
+    assert_eq!(x.call(15, &[Object::Unsigned(1), Object::Unsigned(1)], 1), Err(HaltReason::CycleLimit));
+}
+
+#[test]
+fn failed_call_unwinds() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::AddUnsigned,
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    assert_eq!(x.call(0, &[Object::Bool(true)], 64), Err(HaltReason::TypeError));
+    assert_eq!(x.stack(), &[]);
+
+    assert_eq!(x.call(0, &[Object::Unsigned(1)], 1), Err(HaltReason::CycleLimit));
+    assert_eq!(x.stack(), &[]);
+
+    assert_eq!(x.call(0, &[Object::Unsigned(1)], 64), Ok(&*vec![Object::Unsigned(2)]));
+}
+
+#[test]
+fn record_and_replay_call() {
+    fn push_seven(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        proc.push_results(7u64)
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::AddUnsigned,
+        Instruction::Ret,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        push_seven
+    ];
+
+    let mut buf = [0u8; 64];
+    let mut recorder = LogRecorder::new(&mut buf);
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    assert_eq!(x.call_recording(0, &[Object::Unsigned(1)], 64, &mut recorder), Ok(&*vec![Object::Unsigned(8)]));
+
+    let mut replayer = LogReplayer::new(recorder.log());
+
+    let mut y = Process::new(&ins);
+    assert_eq!(y.call_replaying(0, &[Object::Unsigned(1)], 64, &mut replayer), Ok(&*vec![Object::Unsigned(8)]));
+}
+
+#[test]
+fn record_and_replay_failed_intrinsic() {
+    fn triple(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        match proc.pop_args::<u64>()?.checked_mul(3) {
+            Some(x) => proc.push_results(x),
+            None => Err(HaltReason::Overflow),
+        }
+    }
+
+    let ins = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::Ret,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        triple
+    ];
+
+    let mut buf = [0u8; 64];
+    let mut recorder = LogRecorder::new(&mut buf);
+
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    assert_eq!(x.call_recording(0, &[Object::Unsigned(u64::max_value())], 64, &mut recorder), Err(HaltReason::Overflow));
+    assert_eq!(x.call_recording(0, &[Object::Unsigned(10)], 64, &mut recorder), Ok(&*vec![Object::Unsigned(30)]));
+
+    let mut replayer = LogReplayer::new(recorder.log());
+
+    let mut y = Process::new(&ins);
+    assert_eq!(y.call_replaying(0, &[Object::Unsigned(u64::max_value())], 64, &mut replayer), Err(HaltReason::Overflow));
+    assert_eq!(y.stack(), &[]);
+    assert_eq!(y.call_replaying(0, &[Object::Unsigned(10)], 64, &mut replayer), Ok(&*vec![Object::Unsigned(30)]));
+}
+
+#[test]
+fn call_export_by_name() {
+    let code = vec![
+        Instruction::LiteralUnsigned(1),
+        Instruction::AddUnsigned,
+        Instruction::Ret,
+        Instruction::LiteralBool(true),
+        Instruction::LiteralBool(false),
+        Instruction::Ret,
+    ];
+
+    let exports = vec![
+        Export { name: "increment", entry: 0, arity: 1, results: 1 },
+        Export { name: "flags", entry: 3, arity: 0, results: 1 },
+    ];
+
+    let program = Program {
+        code: &code,
+        literals: &[],
+        strings: &[],
+        exports: &exports,
+    };
+
+    let builder = ProcessBuilder::from_program(&program);
+    assert_eq!(builder.export("flags").map(|e| e.entry), Some(3));
+
+    let mut x = builder.build();
+
+    assert_eq!(x.call_export("increment", &[Object::Unsigned(41)], 64), Ok(&*vec![Object::Unsigned(42)]));
+
+    x.reset();
+    assert_eq!(x.call_export("increment", &[], 64), Err(HaltReason::ArityMismatch));
+    assert_eq!(x.call_export("on_tick", &[], 64), Err(HaltReason::UnknownExport));
+    assert_eq!(x.call_export("flags", &[], 64), Err(HaltReason::ArityMismatch));
+    assert_eq!(x.stack(), &*vec![]);
+}
+
+#[test]
+fn recursive_frame_locals() {
+    // fact(n) = if n == 0 { 1 } else { n * fact(n - 1) }, keeping n in a local
+    let ins = vec![
+        Instruction::Enter(1, 1, 1),
+        Instruction::LoadArg(0),
+        Instruction::StoreLocal(0),
+        Instruction::LoadLocal(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::EqUnsigned,
+        Instruction::LiteralUnsigned(17),
+        Instruction::BranchTrue,
+        Instruction::LoadLocal(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Call,
+        Instruction::LoadLocal(0),
+        Instruction::MultiplyUnsigned,
+        Instruction::Ret,
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    assert_eq!(x.call(0, &[Object::Unsigned(10)], 1000), Ok(&*vec![Object::Unsigned(3628800)]));
+}
+
+#[test]
+fn ret_unwinds_to_frame_base() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(99),
+        Instruction::LiteralUnsigned(1),
+        Instruction::LiteralUnsigned(2),
+        Instruction::LiteralUnsigned(7),
+        Instruction::Call,
+        Instruction::LiteralUnsigned(13),
         Instruction::Branch,
-        Instruction::LiteralUnsigned(100),
-        Instruction::LiteralUnsigned(50),
-        Instruction::LiteralUnsigned(25),
+        Instruction::Enter(2, 0, 1),
+        Instruction::LiteralBool(false),
+        Instruction::LoadArg(1),
+        Instruction::LoadArg(0),
+        Instruction::SubtractUnsigned,
+        Instruction::Ret,
     ];
 
     let mut x = Process::new(&ins);
 
     let ret = x.run(64);
 
-    assert_eq!(x.stack(), &*vec![Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(99), Object::Unsigned(1)]);
     assert_eq!(ret, Err(HaltReason::OutOfBounds))
 }
 
 #[test]
-fn conditional_branch_true() {
+fn frame_errors() {
+    let reason = Process::new(&vec![
+        Instruction::LoadLocal(0),
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::NoFrame));
+
     let ins = vec![
-        Instruction::LiteralBool(true),
-        Instruction::LiteralUnsigned(4),
+        Instruction::Enter(1, 1, 0),
+        Instruction::LoadLocal(0),
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.call(0, &[Object::Unsigned(1)], 64), Err(HaltReason::EmptyLocal));
+
+    let ins = vec![
+        Instruction::Enter(1, 1, 0),
+        Instruction::LoadArg(1),
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.call(0, &[Object::Unsigned(1)], 64), Err(HaltReason::InvalidLocal));
+
+    // Storing the top value into itself pops the argument away.
+    let ins = vec![
+        Instruction::Enter(1, 0, 0),
+        Instruction::StoreArg(0),
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.call(0, &[Object::Unsigned(1)], 64), Err(HaltReason::InvalidLocal));
+
+    let ins = vec![
+        Instruction::Enter(2, 0, 0),
+        Instruction::StoreArg(1),
+    ];
+
+    let mut x = Process::new(&ins);
+    assert_eq!(x.call(0, &[Object::Unsigned(1), Object::Unsigned(2)], 64), Err(HaltReason::InvalidLocal));
+}
+
+#[test]
+fn tail_recursion_does_not_grow_callstack() {
+    // sum(n, acc) = if n == 0 { acc } else { sum(n - 1, acc + n) }
+    let ins = vec![
+        Instruction::Enter(2, 0, 1),
+        Instruction::LoadArg(0),
+        Instruction::LiteralUnsigned(0),
+        Instruction::EqUnsigned,
+        Instruction::LiteralUnsigned(15),
         Instruction::BranchTrue,
+        Instruction::LoadArg(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::LoadArg(1),
+        Instruction::LoadArg(0),
+        Instruction::AddUnsigned,
+        Instruction::LiteralUnsigned(0),
+        Instruction::TailCall(2),
+        Instruction::Ret,
+        Instruction::LoadArg(1),
+        Instruction::Ret,
+    ];
+
+    let mut x = Process::new(&ins);
+
+    assert_eq!(x.call(0, &[Object::Unsigned(10_000), Object::Unsigned(0)], 1_000_000), Ok(&*vec![Object::Unsigned(50_005_000)]));
+
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::TailCall(0),
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::NoFrame));
+}
+
+#[test]
+fn relative_branch() {
+    let ins = vec![
+        Instruction::LiteralSigned(3),
+        Instruction::BranchRelative,
         Instruction::LiteralUnsigned(100),
         Instruction::LiteralUnsigned(50),
         Instruction::LiteralUnsigned(25),
@@ -195,18 +1531,81 @@ fn conditional_branch_true() {
 
     let ret = x.run(64);
 
-    assert_eq!(x.stack(), &*vec![Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(25)]);
     assert_eq!(ret, Err(HaltReason::OutOfBounds))
 }
 
 #[test]
-fn conditional_branch_false() {
+fn relative_loop_and_call_are_position_independent() {
+    // Counts down from 3, calling a relative incrementer each iteration.
+    let body = vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::PushSlot1,
+        Instruction::LiteralUnsigned(3),
+        // loop:
+        Instruction::LiteralSigned(10),
+        Instruction::CallRelative,
+        Instruction::LiteralUnsigned(1),
+        Instruction::SubtractUnsigned,
+        Instruction::PushSlot2,
+        Instruction::PopSlot2,
+        Instruction::PopSlot2,
+        Instruction::LiteralUnsigned(0),
+        Instruction::NeqUnsigned,
+        Instruction::LiteralSigned(-9),
+        Instruction::BranchTrueRelative,
+        Instruction::LiteralSigned(7),
+        Instruction::BranchRelative,
+        // increment scratch 1:
+        Instruction::PopSlot1,
+        Instruction::LiteralUnsigned(1),
+        Instruction::AddUnsigned,
+        Instruction::PushSlot1,
+        Instruction::Ret,
+        Instruction::PopSlot1,
+    ];
+
+    let mut padded = vec![
+        Instruction::LiteralSigned(3),
+        Instruction::BranchRelative,
+        Instruction::Ret,
+        Instruction::Ret,
+    ];
+    padded.extend_from_slice(&body);
+
+    for ins in &[body.clone(), padded] {
+        let mut x = Process::new(ins);
+
+        let ret = x.run(256);
+
+        assert_eq!(x.stack(), &*vec![Object::Unsigned(3)]);
+        assert_eq!(ret, Err(HaltReason::OutOfBounds))
+    }
+}
+
+#[test]
+fn relative_branch_before_start() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralSigned(-5),
+        Instruction::BranchRelative,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn immediate_jumps() {
     let ins = vec![
         Instruction::LiteralBool(false),
-        Instruction::LiteralUnsigned(4),
-        Instruction::BranchTrue,
+        Instruction::JumpIfTrue(0),
+        Instruction::LiteralBool(false),
+        Instruction::JumpIfFalse(5),
+        Instruction::LiteralUnsigned(100),
+        Instruction::LiteralBool(true),
+        Instruction::JumpIfTrue(8),
+        Instruction::LiteralUnsigned(100),
+        Instruction::Jump(10),
         Instruction::LiteralUnsigned(100),
-        Instruction::LiteralUnsigned(50),
         Instruction::LiteralUnsigned(25),
     ];
 
@@ -214,23 +1613,20 @@ fn conditional_branch_false() {
 
     let ret = x.run(64);
 
-    assert_eq!(x.stack(), &*vec![Object::Unsigned(100), Object::Unsigned(50), Object::Unsigned(25)]);
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(25)]);
     assert_eq!(ret, Err(HaltReason::OutOfBounds))
 }
 
 #[test]
-fn calling_incrementer() {
+fn calling_incrementer_immediate() {
     let ins = vec![
-        Instruction::LiteralUnsigned(5),
-        Instruction::Branch,
+        Instruction::Jump(4),
         Instruction::LiteralUnsigned(1),
         Instruction::AddUnsigned,
         Instruction::Ret,
         Instruction::LiteralUnsigned(10),
-        Instruction::LiteralUnsigned(2),
-        Instruction::Call,
-        Instruction::LiteralUnsigned(2),
-        Instruction::Call,
+        Instruction::CallImm(1),
+        Instruction::CallImm(1),
     ];
 
     let mut x = Process::new(&ins);
@@ -242,135 +1638,211 @@ fn calling_incrementer() {
 }
 
 #[test]
-#[should_panic(expected = "Called expected_panic from intrinsic")]
-fn simple_intrinsic() {
-
-    fn expected_panic(proc: &mut rlang::Process) {
-        panic!("Called expected_panic from intrinsic")
-    }
-
+fn switch_jump_table() {
     let ins = vec![
-        Instruction::LiteralUnsigned(0),
-        Instruction::Intrinsic,
+        Instruction::Switch(1),
+        Instruction::LiteralUnsigned(10),
+        Instruction::Jump(6),
+        Instruction::LiteralUnsigned(20),
+        Instruction::Jump(6),
+        Instruction::LiteralUnsigned(99),
     ];
 
-    let intrinsics: &[fn(&mut Process)] = &[
-        expected_panic
+    let literals = vec![
+        Object::Bool(false),
+        Object::Unsigned(2),
+        Object::Unsigned(5),
+        Object::Unsigned(1),
+        Object::Unsigned(3),
     ];
 
-    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
 
-    let ret = x.run(64);
+    for &(selector, result) in &[(0, 10), (1, 20), (2, 99), (1000, 99)] {
+        x.reset_with_args(&[Object::Unsigned(selector)]).unwrap();
+        let ret = x.run(64);
 
-    assert!(false);
+        assert_eq!(x.stack(), &*vec![Object::Unsigned(result)]);
+        assert_eq!(ret, Err(HaltReason::OutOfBounds))
+    }
 }
 
 #[test]
-fn intrinsic_mutation() {
-    fn triple_top(proc: &mut rlang::Process) {
-        let top = proc.pub_pop_as::<u64>().unwrap();
-        let new_top = top * 3;
-        assert!(proc.pub_push(Object::Unsigned(new_top)));
-    }
-
+fn invalid_switch_table() {
     let ins = vec![
-        Instruction::LiteralUnsigned(13),
         Instruction::LiteralUnsigned(0),
-        Instruction::Intrinsic,
+        Instruction::Switch(0),
     ];
 
-    let intrinsics: &[fn(&mut Process)] = &[
-        triple_top
+    let literals = vec![
+        Object::Unsigned(3),
+        Object::Unsigned(0),
+        Object::Unsigned(0),
     ];
 
-    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).build();
+    let reason = ProcessBuilder::new(&ins).literals(&literals[..]).build().run(64);
 
-    let ret = x.run(64);
+    assert_eq!(reason, Err(HaltReason::InvalidSwitchTable));
 
-    assert_eq!(x.stack(), &*vec![Object::Unsigned(39)]);
-    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+    let reason = Process::new(&ins).run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidSwitchTable));
+
+    // A count that would wrap around must not alias the table itself.
+    let literals = vec![
+        Object::Unsigned(u64::max_value()),
+        Object::Unsigned(0),
+    ];
+
+    let reason = ProcessBuilder::new(&ins).literals(&literals[..]).build().run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidSwitchTable))
 }
 
 #[test]
-fn push_indexed_literal() {
+fn linear_memory_load_store() {
     let ins = vec![
+        Instruction::LiteralUnsigned(0),
         Instruction::LiteralIndexed(0),
+        Instruction::StoreU64,
+        Instruction::LiteralUnsigned(8),
+        Instruction::LiteralUnsigned(0x1234),
+        Instruction::StoreU8,
+        Instruction::LiteralUnsigned(2),
+        Instruction::LoadU16,
+        Instruction::LiteralUnsigned(4),
+        Instruction::LoadU32,
+        Instruction::LiteralUnsigned(1),
+        Instruction::LoadU64,
     ];
-    
+
     let literals = vec![
-        Object::Unsigned(50),
+        Object::Unsigned(0x0807_0605_0403_0201),
     ];
 
-    let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).build();
+    let mut memory = [0u8; 9];
 
-    let ret = x.run(64);
+    {
+        let mut x = ProcessBuilder::new(&ins).literals(&literals[..]).memory(&mut memory).build();
 
-    assert_eq!(x.stack(), &*vec![Object::Unsigned(50)]);
-    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+        let ret = x.run(64);
+
+        assert_eq!(x.stack(), &*vec![
+            Object::Unsigned(0x0403),
+            Object::Unsigned(0x0807_0605),
+            Object::Unsigned(0x3408_0706_0504_0302),
+        ]);
+        assert_eq!(ret, Err(HaltReason::OutOfBounds));
+    }
+
+    assert_eq!(memory, [1, 2, 3, 4, 5, 6, 7, 8, 0x34]);
 }
 
 #[test]
-fn stack_underflow() {
+fn memory_out_of_bounds() {
     let ins = vec![
-        Instruction::AddUnsigned,
+        Instruction::LiteralUnsigned(6),
+        Instruction::LoadU32,
     ];
 
-    let mut x = Process::new(&ins);
+    let mut memory = [0u8; 8];
 
-    let reason = x.run(64);
+    let reason = ProcessBuilder::new(&ins).memory(&mut memory).build().run(64);
 
-    assert_eq!(reason, Err(HaltReason::StackUnderflow))
+    assert_eq!(reason, Err(HaltReason::MemoryOutOfBounds));
+
+    let reason = Process::new(&vec![
+        Instruction::LiteralUnsigned(0),
+        Instruction::LoadU8,
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::MemoryOutOfBounds))
 }
 
 #[test]
-fn type_error() {
+fn globals_persist_across_calls() {
     let ins = vec![
-        Instruction::LiteralUnsigned(1),
-        Instruction::LiteralSigned(1),
-        Instruction::AddUnsigned
+        Instruction::LoadGlobal(0),
+        Instruction::LoadGlobal(1),
+        Instruction::AddUnsigned,
+        Instruction::StoreGlobal(0),
+        Instruction::Ret,
     ];
 
-    let mut x = Process::new(&ins);
+    let mut globals = [Some(Object::Unsigned(0)), None];
 
-    let reason = x.run(64);
+    {
+        let mut x = ProcessBuilder::new(&ins).globals(&mut globals).build();
 
-    assert_eq!(reason, Err(HaltReason::TypeError))
-}
+        assert_eq!(x.call(0, &[], 64), Err(HaltReason::EmptyGlobal));
 
-#[test]
-fn empty_scratch() {
-    let reason = Process::new(&vec![
-        Instruction::PopSlot1,
-    ]).run(64);
+        assert!(x.set_global(1, Object::Unsigned(5)));
+        assert!(!x.set_global(2, Object::Unsigned(5)));
 
-    assert_eq!(reason, Err(HaltReason::EmptyScratch))
+        for _ in 0..3 {
+            x.reset();
+            assert_eq!(x.call(0, &[], 64), Ok(&*vec![]));
+        }
+
+        assert_eq!(x.global(0), Some(Object::Unsigned(15)));
+        assert_eq!(x.global(2), None);
+    }
+
+    assert_eq!(globals, [Some(Object::Unsigned(15)), Some(Object::Unsigned(5))]);
 }
 
 #[test]
-fn cycle_limit() {
+fn invalid_global() {
     let reason = Process::new(&vec![
-        Instruction::LiteralUnsigned(0),
-        Instruction::Branch,
+        Instruction::LoadGlobal(0),
     ]).run(64);
 
-    assert_eq!(reason, Err(HaltReason::CycleLimit))
+    assert_eq!(reason, Err(HaltReason::InvalidGlobal))
 }
 
 #[test]
-fn invalid_intrinsic() {
-    let reason = Process::new(&vec![
-        Instruction::LiteralUnsigned(0),
-        Instruction::Intrinsic,
-    ]).run(64);
+fn register_file() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(3),
+        Instruction::StoreRegKeep(9),
+        Instruction::StoreReg(0),
+        Instruction::LoadReg(9),
+        Instruction::LoadReg(0),
+        Instruction::AddUnsigned,
+        Instruction::PushSlot2,
+        Instruction::LoadReg(1),
+        Instruction::ClearReg(9),
+        Instruction::LoadReg(9),
+    ];
 
-    assert_eq!(reason, Err(HaltReason::InvalidIntrinsic))
+    let mut x = ProcessBuilder::new(&ins).registers(10).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(6)]);
+    assert_eq!(ret, Err(HaltReason::EmptyScratch))
 }
 
 #[test]
-fn invalid_literal() {
+fn invalid_register() {
     let reason = Process::new(&vec![
-        Instruction::LiteralIndexed(0),
+        Instruction::LiteralUnsigned(3),
+        Instruction::StoreReg(4),
     ]).run(64);
 
-    assert_eq!(reason, Err(HaltReason::InvalidLiteral))
+    assert_eq!(reason, Err(HaltReason::InvalidRegister));
+
+    let reason = ProcessBuilder::new(&vec![
+        Instruction::LiteralUnsigned(3),
+        Instruction::PushSlot1,
+    ]).registers(0).build().run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidRegister));
+
+    let reason = ProcessBuilder::new(&vec![
+        Instruction::LiteralUnsigned(3),
+        Instruction::StoreReg(16),
+    ]).registers(200).build().run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidRegister))
 }
@@ -0,0 +1,222 @@
+extern crate rlang;
+use rlang::*;
+
+#[test]
+fn literal_length_and_bytes() {
+    let ins = vec![
+        Instruction::LiteralStr(0),
+        Instruction::StrLength,
+        Instruction::LiteralStr(0),
+        Instruction::LiteralUnsigned(1),
+        Instruction::StrByte,
+        Instruction::LiteralStr(0),
+        Instruction::LiteralUnsigned(5),
+        Instruction::StrByte,
+    ];
+
+    let strings: &[&[u8]] = &[b"hello"];
+    let mut x = ProcessBuilder::new(&ins).strings(strings).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Unsigned(5), Object::Unsigned(u64::from(b'e'))]);
+    assert_eq!(ret, Err(HaltReason::IndexOutOfBounds))
+}
+
+#[test]
+fn slice_and_compare() {
+    let ins = vec![
+        Instruction::LiteralStr(0),
+        Instruction::LiteralUnsigned(4),
+        Instruction::LiteralUnsigned(7),
+        Instruction::StrSlice,
+        Instruction::LiteralStr(1),
+        Instruction::StrCompare,
+        Instruction::LiteralStr(1),
+        Instruction::LiteralStr(0),
+        Instruction::StrCompare,
+        Instruction::LiteralStr(0),
+        Instruction::LiteralStr(1),
+        Instruction::StrCompare,
+        Instruction::LiteralStr(1),
+        Instruction::LiteralStr(2),
+        Instruction::StrCompare,
+    ];
+
+    let strings: &[&[u8]] = &[b"set led on", b"led", b"lee"];
+    let mut x = ProcessBuilder::new(&ins).strings(strings).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Signed(0),
+        Object::Signed(-1),
+        Object::Signed(1),
+        Object::Signed(-1),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn concat_and_format() {
+    let ins = vec![
+        Instruction::LiteralUnsigned(16),
+        Instruction::MakeBytes,
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralStr(0),
+        Instruction::StrConcat,
+        Instruction::LiteralSigned(-42),
+        Instruction::FormatSigned,
+        Instruction::LiteralStr(1),
+        Instruction::StrConcat,
+        Instruction::LiteralIndexed(0),
+        Instruction::FormatUnsigned,
+    ];
+
+    let literals = [Object::Unsigned(1234567)];
+    let strings: &[&[u8]] = &[b"t=", b", "];
+    let mut arena = [Object::Unsigned(0); 8];
+    let mut x = ProcessBuilder::new(&ins)
+        .literals(&literals)
+        .strings(strings)
+        .heap(&mut arena)
+        .build();
+
+    let ret = x.run(64);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds));
+
+    let stack = x.stack().to_vec();
+    assert_eq!(stack[1], Object::Unsigned(14));
+
+    let mut buf = [0; 16];
+    assert_eq!(x.read_string(stack[0], &mut buf), Ok(&b"t=-42, 1234567\0\0"[..]));
+}
+
+#[test]
+fn format_out_of_bounds() {
+    let reason = ProcessBuilder::new(&vec![
+        Instruction::LiteralUnsigned(2),
+        Instruction::MakeBytes,
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralUnsigned(100),
+        Instruction::FormatUnsigned,
+    ]).heap(&mut [Object::Unsigned(0); 4]).build().run(64);
+
+    assert_eq!(reason, Err(HaltReason::IndexOutOfBounds))
+}
+
+#[test]
+fn parse() {
+    let ins = vec![
+        Instruction::LiteralStr(0),
+        Instruction::ParseUnsigned,
+        Instruction::LiteralStr(1),
+        Instruction::ParseSigned,
+        Instruction::LiteralStr(2),
+        Instruction::ParseSigned,
+        Instruction::LiteralStr(3),
+        Instruction::ParseUnsigned,
+        Instruction::LiteralStr(4),
+        Instruction::ParseUnsigned,
+    ];
+
+    let strings: &[&[u8]] = &[
+        b"18446744073709551615",
+        b"-9223372036854775808",
+        b"9223372036854775808",
+        b"12a",
+        b"",
+    ];
+    let mut x = ProcessBuilder::new(&ins).strings(strings).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Unsigned(u64::max_value()),
+        Object::Bool(true),
+        Object::Signed(i64::min_value()),
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(false),
+        Object::Bool(false),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn invalid_string_literal() {
+    let reason = Process::new(&vec![
+        Instruction::LiteralStr(0),
+    ]).run(64);
+
+    assert_eq!(reason, Err(HaltReason::InvalidLiteral))
+}
+
+#[test]
+fn equality_compares_contents() {
+    let ins = vec![
+        Instruction::LiteralStr(0),
+        Instruction::LiteralUnsigned(4),
+        Instruction::LiteralUnsigned(7),
+        Instruction::StrSlice,
+        Instruction::LiteralStr(1),
+        Instruction::Eq,
+        Instruction::LiteralUnsigned(3),
+        Instruction::MakeBytes,
+        Instruction::LiteralUnsigned(0),
+        Instruction::LiteralStr(1),
+        Instruction::StrConcat,
+        Instruction::PushSlot1,
+        Instruction::LiteralStr(1),
+        Instruction::Eq,
+        Instruction::LiteralStr(2),
+        Instruction::LiteralStr(1),
+        Instruction::Compare,
+        Instruction::LiteralStr(1),
+        Instruction::LiteralStr(2),
+        Instruction::Neq,
+    ];
+
+    let strings: &[&[u8]] = &[b"set led on", b"led", b"lee"];
+    let mut arena = [Object::Unsigned(0); 8];
+    let mut x = ProcessBuilder::new(&ins).strings(strings).heap(&mut arena).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Signed(1),
+        Object::Bool(true),
+    ]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}
+
+#[test]
+fn intrinsic_str_args() {
+    fn check_strs(proc: &mut rlang::Process) -> Result<(), HaltReason> {
+        let missing = proc.pop_args::<Option<StrRef>>()?;
+        let s = proc.pop_args::<StrRef>()?;
+        proc.push_results((missing.is_none(), s))
+    }
+
+    let ins = vec![
+        Instruction::LiteralStr(0),
+        Instruction::LiteralNil,
+        Instruction::LiteralUnsigned(0),
+        Instruction::Intrinsic,
+        Instruction::StrLength,
+    ];
+
+    let intrinsics: &[IntrinsicFn] = &[
+        check_strs
+    ];
+
+    let strings: &[&[u8]] = &[b"hello"];
+    let mut x = ProcessBuilder::new(&ins).intrinsics(&intrinsics[..]).strings(strings).build();
+
+    let ret = x.run(64);
+
+    assert_eq!(x.stack(), &*vec![Object::Bool(true), Object::Unsigned(5)]);
+    assert_eq!(ret, Err(HaltReason::OutOfBounds))
+}